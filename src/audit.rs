@@ -0,0 +1,77 @@
+use serde_json::json;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use log::warn;
+use crate::RestaurantDetails;
+
+const DEFAULT_AUDIT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const DEFAULT_AUDIT_LOG_MAX_FILES: u32 = 5;
+
+lazy_static::lazy_static! {
+    /// Keeps lines from concurrent requests, and rotation, from interleaving.
+    static ref AUDIT_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// Appends one JSON line per processed request to `AUDIT_LOG_PATH`, when set: the
+/// input, the resolved details (if resolution got that far) and the outcome.
+pub fn record(request_id: &str, input: &str, details: Option<&RestaurantDetails>, result: &Result<String, String>) {
+    let path = match env::var("AUDIT_LOG_PATH") {
+        Ok(path) if !path.is_empty() => path,
+        _ => return,
+    };
+
+    let (status, message) = match result {
+        Ok(message) => ("success", message),
+        Err(message) => ("error", message),
+    };
+    let line = json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "request_id": request_id,
+        "input": input,
+        "details": details,
+        "status": status,
+        "message": message,
+    });
+
+    let _guard = AUDIT_LOCK.lock().unwrap();
+    let result = rotate_if_needed(&path).and_then(|_| {
+        if let Some(dir) = Path::new(&path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        writeln!(file, "{}", line)
+    });
+
+    if let Err(e) = result {
+        warn!("Failed to write audit log {}: {}", path, e);
+    }
+}
+
+/// Once the log reaches `AUDIT_LOG_MAX_BYTES` (default 10 MB), shifts it to `<path>.1`,
+/// `<path>.1` to `<path>.2` and so on, keeping `AUDIT_LOG_MAX_FILES` old files (default 5).
+fn rotate_if_needed(path: &str) -> std::io::Result<()> {
+    let max_bytes = env::var("AUDIT_LOG_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_AUDIT_LOG_MAX_BYTES);
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.len() >= max_bytes => {}
+        _ => return Ok(()),
+    }
+
+    let max_files = env::var("AUDIT_LOG_MAX_FILES")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_AUDIT_LOG_MAX_FILES)
+        .max(1);
+    for index in (1..max_files).rev() {
+        let from = format!("{}.{}", path, index);
+        if Path::new(&from).exists() {
+            fs::rename(&from, format!("{}.{}", path, index + 1))?;
+        }
+    }
+    fs::rename(path, format!("{}.1", path))
+}
@@ -1,11 +1,23 @@
 use reqwest::Client;
 use serde_json::Value;
 use std::env;
-use log::{info, error, debug};
+use crate::error::GastropathError;
+use crate::utils::{Provider, RequestBuilderExt};
+use log::{info, error, debug, warn};
 use sha1::{Sha1, Digest};
 
+const DEFAULT_CLOUDINARY_CONCURRENCY: usize = 2;
+
 lazy_static::lazy_static! {
-    static ref CLOUDINARY_CONFIG: CloudinaryConfig = CloudinaryConfig::new();
+    static ref CLOUDINARY_CONFIG: Result<CloudinaryConfig, String> = CloudinaryConfig::from_env();
+    /// Caps concurrent uploads at `CLOUDINARY_CONCURRENCY` (default 2), whatever else runs in parallel.
+    static ref UPLOAD_SLOTS: tokio::sync::Semaphore = tokio::sync::Semaphore::new(
+        env::var("CLOUDINARY_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_CLOUDINARY_CONCURRENCY)
+    );
 }
 
 struct CloudinaryConfig {
@@ -15,66 +27,95 @@ struct CloudinaryConfig {
 }
 
 impl CloudinaryConfig {
-    fn new() -> Self {
-        Self {
-            cloud_name: env::var("CLOUDINARY_CLOUD_NAME").expect("CLOUDINARY_CLOUD_NAME must be set"),
-            api_key: env::var("CLOUDINARY_API_KEY").expect("CLOUDINARY_API_KEY must be set"),
-            api_secret: env::var("CLOUDINARY_API_SECRET").expect("CLOUDINARY_API_SECRET must be set"),
-        }
+    fn from_env() -> Result<Self, String> {
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty()).ok_or_else(|| format!("{} must be set", name));
+        Ok(Self {
+            cloud_name: var("CLOUDINARY_CLOUD_NAME")?,
+            api_key: var("CLOUDINARY_API_KEY")?,
+            api_secret: var("CLOUDINARY_API_SECRET")?,
+        })
     }
 }
 
-pub async fn upload_image(client: &Client, photo_reference: &Option<String>) -> Result<String, Box<dyn std::error::Error>> {
-    if let Some(reference) = photo_reference {
-        info!("Uploading image to Cloudinary");
-        let google_api_key = env::var("GOOGLE_API_KEY")?;
-
-        let photo_url = format!(
-            "https://maps.googleapis.com/maps/api/place/photo?maxwidth=800&photoreference={}&key={}",
-            reference, google_api_key
-        );
-
-        debug!("Generated photo URL: {}", photo_url);
-
-        let timestamp = chrono::Utc::now().timestamp();
-        let signature_string = format!("timestamp={}{}", timestamp, CLOUDINARY_CONFIG.api_secret);
-        let signature = Sha1::digest(signature_string.as_bytes());
-        let signature = format!("{:x}", signature);
-
-        let form = reqwest::multipart::Form::new()
-            .text("file", photo_url)
-            .text("api_key", CLOUDINARY_CONFIG.api_key.clone())
-            .text("timestamp", timestamp.to_string())
-            .text("signature", signature);
-
-        let upload_url = format!(
-            "https://api.cloudinary.com/v1_1/{}/image/upload",
-            CLOUDINARY_CONFIG.cloud_name
-        );
-
-        debug!("Sending request to Cloudinary API: {}", upload_url);
-
-        let response = client.post(&upload_url)
-            .multipart(form)
-            .send()
-            .await?
-            .json::<Value>()
-            .await?;
-
-        debug!("Received response from Cloudinary: {:?}", response);
-
-        if let Some(secure_url) = response["secure_url"].as_str() {
-            info!("Successfully uploaded image to Cloudinary");
-            Ok(secure_url.to_string())
-        } else {
-            let error_message = "Failed to upload image to Cloudinary";
+/// Checks at startup that the Cloudinary credentials are set.
+pub fn validate_config() -> Result<(), String> {
+    CLOUDINARY_CONFIG.as_ref().map(|_| ()).map_err(|e| format!("Cloudinary is not configured: {}", e))
+}
+
+/// Uploads each of the photos to Cloudinary, returning their URLs (or why an upload
+/// failed) in the same order. One failing photo doesn't affect the others.
+pub async fn upload_images(client: &Client, references: &[&str]) -> Vec<Result<String, GastropathError>> {
+    let config = match CLOUDINARY_CONFIG.as_ref() {
+        Ok(config) => config,
+        Err(e) => {
+            let error_message = format!("Cloudinary is not configured: {}", e);
             error!("{}", error_message);
-            Err(error_message.into())
+            return references.iter().map(|_| Err(GastropathError::Other(error_message.clone()))).collect();
         }
+    };
+    futures::future::join_all(references.iter().map(|reference| upload_image(client, config, reference))).await
+}
+
+async fn upload_image(client: &Client, config: &CloudinaryConfig, reference: &str) -> Result<String, GastropathError> {
+    // Download first so an undersized photo is rejected before anything is stored
+    let (bytes, content_type) = crate::google_places::download_photo(client, reference).await?;
+    check_minimums(crate::google_places::image_dimensions(&bytes), bytes.len() as u64)?;
+    info!("Uploading image to Cloudinary");
+
+    let timestamp = chrono::Utc::now().timestamp();
+    let signature_string = format!("timestamp={}{}", timestamp, config.api_secret);
+    let signature = Sha1::digest(signature_string.as_bytes());
+    let signature = format!("{:x}", signature);
+
+    let form = reqwest::multipart::Form::new()
+        .part("file", reqwest::multipart::Part::bytes(bytes).file_name("photo").mime_str(&content_type)?)
+        .text("api_key", config.api_key.clone())
+        .text("timestamp", timestamp.to_string())
+        .text("signature", signature);
+
+    let upload_url = format!(
+        "{}/v1_1/{}/image/upload",
+        crate::utils::api_base(Provider::Cloudinary), config.cloud_name
+    );
+
+    debug!("Sending request to Cloudinary API: {}", upload_url);
+
+    let _slot = UPLOAD_SLOTS.acquire().await.map_err(|e| GastropathError::Other(e.to_string()))?;
+    let response = client.post(&upload_url)
+        .provider_timeout("CLOUDINARY")
+        .multipart(form)
+        .send()
+        .await?
+        .json::<Value>()
+        .await?;
+
+    debug!("Received response from Cloudinary: {:?}", response);
+
+    if let Some(secure_url) = response["secure_url"].as_str() {
+        info!("Successfully uploaded image to Cloudinary");
+        Ok(secure_url.to_string())
     } else {
-        let error_message = "No photo reference provided";
+        let error_message = "Failed to upload image to Cloudinary";
         error!("{}", error_message);
-        Err(error_message.into())
+        Err(GastropathError::Cloudinary(error_message.to_string()))
     }
 }
 
+
+/// Rejects photos below `CLOUDINARY_MIN_WIDTH`/`CLOUDINARY_MIN_HEIGHT`/`CLOUDINARY_MIN_BYTES`,
+/// which usually means Google served an error placeholder instead of a photo. The width and
+/// height are only checked when they could be read from the image header.
+fn check_minimums(dimensions: Option<(u64, u64)>, bytes: u64) -> Result<(), GastropathError> {
+    let (width, height) = (dimensions.map(|(width, _)| width), dimensions.map(|(_, height)| height));
+    for (field, actual, var) in [("width", width, "CLOUDINARY_MIN_WIDTH"), ("height", height, "CLOUDINARY_MIN_HEIGHT"), ("bytes", Some(bytes), "CLOUDINARY_MIN_BYTES")] {
+        let (Some(actual), Some(minimum)) = (actual, env::var(var).ok().and_then(|v| v.parse::<u64>().ok())) else {
+            continue;
+        };
+        if actual < minimum {
+            let error_message = format!("Image rejected before upload: {} {} is below the minimum of {}", field, actual, minimum);
+            warn!("{}", error_message);
+            return Err(GastropathError::Cloudinary(error_message));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use reqwest::Client;
+use std::env;
+use crate::utils::{Provider, RequestBuilderExt};
+
+/// Validates the whole configuration without starting the server: the same checks as
+/// startup, plus the variables each enabled feature needs. With `ping`, also reads the
+/// Notion database to prove the credentials work. Nothing is written anywhere.
+/// Prints a report and returns whether everything passed.
+pub async fn run(client: &Client, ping: bool) -> bool {
+    let mut results: Vec<(String, Result<(), String>)> = vec![
+        ("Price settings".to_string(), crate::google_places::validate_price_config()),
+        ("NOTION_PROPERTY_TYPES".to_string(), crate::notion::validate_property_types()),
+        ("NOTION_PROP_*".to_string(), crate::notion::validate_property_map()),
+        ("RATING_TIERS".to_string(), crate::notion::validate_rating_tiers()),
+        ("PLACE_PROVIDER".to_string(), crate::place_provider::validate()),
+        ("METRICS_EXPORTER".to_string(), crate::metrics::validate_exporter()),
+        ("RATE_LIMIT_TRUSTED_IPS".to_string(), crate::rate_limit::ClientKeyExtractor::from_env().map(|_| ())),
+        ("Google API key".to_string(), crate::google_places::current_api_key().map(|_| ()).map_err(|e| e.to_string())),
+    ];
+
+    let mut required = vec!["NOTION_API_KEY", "NOTION_DATABASE_ID"];
+    if env::var("COVER_STORAGE").as_deref() != Ok("notion") {
+        required.extend(["CLOUDINARY_CLOUD_NAME", "CLOUDINARY_API_KEY", "CLOUDINARY_API_SECRET"]);
+    }
+    required.extend(crate::cuisine::required_keys());
+    for var in required {
+        let result = match env::var(var) {
+            Ok(value) if !value.is_empty() => Ok(()),
+            _ => Err("not set".to_string()),
+        };
+        results.push((var.to_string(), result));
+    }
+
+    if ping {
+        results.push(("Notion database access".to_string(), ping_notion(client).await));
+    }
+
+    let mut passed = true;
+    for (name, result) in &results {
+        match result {
+            Ok(()) => println!("ok      {}", name),
+            Err(e) => {
+                passed = false;
+                println!("FAILED  {}: {}", name, e);
+            }
+        }
+    }
+    println!("{}", if passed { "Configuration is valid" } else { "Configuration has errors" });
+    passed
+}
+
+/// Retrieves the database, a read-only call.
+async fn ping_notion(client: &Client) -> Result<(), String> {
+    let api_key = env::var("NOTION_API_KEY").map_err(|_| "NOTION_API_KEY not set".to_string())?;
+    let database_id = env::var("NOTION_DATABASE_ID").map_err(|_| "NOTION_DATABASE_ID not set".to_string())?;
+    let response = client.get(format!("{}/v1/databases/{}", crate::utils::api_base(Provider::Notion), database_id))
+        .provider_timeout("NOTION")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Notion-Version", "2022-06-28")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", response.status()))
+    }
+}
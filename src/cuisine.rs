@@ -0,0 +1,194 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use std::env;
+use log::{info, warn};
+use crate::error::GastropathError;
+use crate::google_places::{self, PlaceDetails};
+
+/// What a cuisine provider found for a place.
+#[derive(Debug, Default)]
+pub struct CuisineMatch {
+    /// The categories, or `None` when there is no (confident) match.
+    pub cuisine: Option<String>,
+    /// Yelp's rating of the place, when Yelp was asked and matched it.
+    pub yelp_rating: Option<f64>,
+}
+
+/// An external source of cuisine categories, enabled through `CUISINE_PROVIDERS`.
+#[async_trait(?Send)]
+pub trait CuisineProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// API key variables the provider needs.
+    fn required_keys(&self) -> Vec<&'static str>;
+
+    async fn lookup(&self, client: &Client, place: &PlaceDetails, request_id: &str) -> Result<CuisineMatch, GastropathError>;
+}
+
+/// A provider's answer, with its ❓ placeholder meaning no match.
+fn known(cuisine: String) -> Option<String> {
+    Some(cuisine).filter(|cuisine| cuisine != "❓")
+}
+
+pub struct Yelp;
+
+#[async_trait(?Send)]
+impl CuisineProvider for Yelp {
+    fn name(&self) -> &'static str {
+        "yelp"
+    }
+
+    fn required_keys(&self) -> Vec<&'static str> {
+        vec!["YELP_API_KEY"]
+    }
+
+    async fn lookup(&self, client: &Client, place: &PlaceDetails, request_id: &str) -> Result<CuisineMatch, GastropathError> {
+        let found = crate::yelp::get_cuisine_type(client, &place.name, &place.city, place.coordinates, request_id).await?;
+        Ok(CuisineMatch { cuisine: known(found.cuisine), yelp_rating: found.rating })
+    }
+}
+
+pub struct Foursquare;
+
+#[async_trait(?Send)]
+impl CuisineProvider for Foursquare {
+    fn name(&self) -> &'static str {
+        "foursquare"
+    }
+
+    fn required_keys(&self) -> Vec<&'static str> {
+        vec!["FOURSQUARE_API_KEY"]
+    }
+
+    async fn lookup(&self, client: &Client, place: &PlaceDetails, request_id: &str) -> Result<CuisineMatch, GastropathError> {
+        let cuisine = crate::foursquare::get_cuisine_type(client, &place.name, &place.city, place.coordinates, request_id).await?;
+        Ok(CuisineMatch { cuisine: known(cuisine), yelp_rating: None })
+    }
+}
+
+/// Queries several providers concurrently. With `first_only`, the first provider (in
+/// order) with an answer wins; otherwise all their categories are merged. A provider
+/// that fails is logged and counts as having no answer.
+pub struct Composite {
+    providers: Vec<Box<dyn CuisineProvider>>,
+    first_only: bool,
+}
+
+impl Composite {
+    /// The providers in `CUISINE_PROVIDERS`, combined as `CUISINE_MERGE` says: `first`,
+    /// or `union` (the default).
+    fn from_env() -> Self {
+        Composite { providers: configured_providers(), first_only: env::var("CUISINE_MERGE").as_deref() == Ok("first") }
+    }
+}
+
+#[async_trait(?Send)]
+impl CuisineProvider for Composite {
+    fn name(&self) -> &'static str {
+        "composite"
+    }
+
+    fn required_keys(&self) -> Vec<&'static str> {
+        self.providers.iter().flat_map(|provider| provider.required_keys()).collect()
+    }
+
+    async fn lookup(&self, client: &Client, place: &PlaceDetails, request_id: &str) -> Result<CuisineMatch, GastropathError> {
+        let results = futures::future::join_all(self.providers.iter().map(|provider| provider.lookup(client, place, request_id))).await;
+
+        let mut found = Vec::new();
+        let mut yelp_rating = None;
+        for (provider, result) in self.providers.iter().zip(results) {
+            match result {
+                Ok(result) => {
+                    if let Some(cuisine) = result.cuisine {
+                        info!("Updating {} - Cuisine Type from {}: {}", place.name, provider.name(), cuisine);
+                        found.push(cuisine);
+                    }
+                    yelp_rating = yelp_rating.or(result.yelp_rating);
+                }
+                Err(e) => warn!("Failed to get cuisine type for {} from {}: {}", place.name, provider.name(), e),
+            }
+        }
+
+        let cuisine = if self.first_only { found.into_iter().next() } else { merge(found.iter()) };
+        Ok(CuisineMatch { cuisine, yelp_rating })
+    }
+}
+
+fn from_name(name: &str) -> Option<Box<dyn CuisineProvider>> {
+    match name {
+        "yelp" => Some(Box::new(Yelp)),
+        "foursquare" => Some(Box::new(Foursquare)),
+        _ => None,
+    }
+}
+
+/// API key variables the configured providers need.
+pub fn required_keys() -> Vec<&'static str> {
+    Composite::from_env().required_keys()
+}
+
+/// Providers listed in `CUISINE_PROVIDERS` (comma-separated, default `yelp`), in order.
+fn configured_providers() -> Vec<Box<dyn CuisineProvider>> {
+    let mut providers: Vec<Box<dyn CuisineProvider>> = Vec::new();
+    for name in env::var("CUISINE_PROVIDERS").unwrap_or_else(|_| "yelp".to_string()).split(',') {
+        let name = name.trim().to_lowercase();
+        match from_name(&name) {
+            Some(provider) if !providers.iter().any(|p| p.name() == provider.name()) => providers.push(provider),
+            Some(_) => {}
+            None if name.is_empty() => {}
+            None => warn!("Ignoring unknown cuisine provider '{}'", name),
+        }
+    }
+    providers
+}
+
+/// The chosen cuisine, plus Yelp's rating when Yelp was asked and matched the place.
+pub struct Cuisine {
+    pub name: String,
+    pub yelp_rating: Option<f64>,
+}
+
+/// Picks the cuisine from Google types and the external providers. `CUISINE_SOURCE_ORDER`
+/// (`yelp,google` by default, where `yelp` stands for all `CUISINE_PROVIDERS`) sets which
+/// source is preferred; the first one with an answer wins, unless `CUISINE_COMBINE=true`
+/// merges both in that order.
+pub async fn resolve(client: &Client, place_details: &PlaceDetails, request_id: &str) -> Cuisine {
+    let google_cuisine = google_places::cuisine_from_types(&place_details.types);
+    let order = env::var("CUISINE_SOURCE_ORDER").unwrap_or_else(|_| "yelp,google".to_string());
+    let google_first = order.split(',').map(str::trim).find(|s| *s == "google" || *s == "yelp") == Some("google");
+    let combine = env::var("CUISINE_COMBINE").map(|v| v == "true" || v == "1").unwrap_or(false);
+    let skip_yelp = env::var("SKIP_YELP_WHEN_GOOGLE_TYPES").map(|v| v == "true" || v == "1").unwrap_or(false);
+
+    if let Some(cuisine) = google_cuisine.as_ref().filter(|_| (skip_yelp || google_first) && !combine) {
+        info!("Skipping cuisine providers for {}, Google types give cuisine: {}", place_details.name, cuisine);
+        return Cuisine { name: cuisine.clone(), yelp_rating: None };
+    }
+
+    let CuisineMatch { cuisine: provider_cuisine, yelp_rating } =
+        Composite::from_env().lookup(client, place_details, request_id).await.unwrap_or_default();
+
+    let (primary, secondary) = if google_first { (google_cuisine, provider_cuisine) } else { (provider_cuisine, google_cuisine) };
+    let cuisine = if combine {
+        merge([primary, secondary].iter().flatten())
+    } else {
+        primary.or(secondary)
+    };
+    Cuisine { name: cuisine.unwrap_or_else(|| "❓".to_string()), yelp_rating }
+}
+
+/// Joins comma-separated cuisine lists, dropping duplicates (ignoring case and any
+/// leading emoji) while keeping the first occurrence's position.
+fn merge<'a>(cuisines: impl Iterator<Item = &'a String>) -> Option<String> {
+    let mut merged: Vec<&str> = Vec::new();
+    for cuisine in cuisines.flat_map(|c| c.split(", ")) {
+        if !merged.iter().any(|m| google_places::strip_cuisine_emoji(m).eq_ignore_ascii_case(google_places::strip_cuisine_emoji(cuisine))) {
+            merged.push(cuisine);
+        }
+    }
+    if merged.is_empty() {
+        None
+    } else {
+        Some(merged.join(", "))
+    }
+}
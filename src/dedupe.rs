@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use log::info;
+use tokio::sync::OwnedMutexGuard;
+
+const DEFAULT_DEDUPE_WINDOW_SECS: u64 = 30;
+
+/// When an add succeeded, and its message.
+type RecentResult = (Instant, String);
+
+lazy_static::lazy_static! {
+    static ref RECENT_RESULTS: Mutex<HashMap<String, RecentResult>> = Mutex::new(HashMap::new());
+    /// One lock per place_id being added, held from the dedupe check until the outcome is known.
+    static ref IN_FLIGHT: KeyedLocks = KeyedLocks::default();
+}
+
+/// Async locks by key (a place_id, a slug), each forgotten once no request holds or
+/// waits for it.
+#[derive(Default)]
+pub struct KeyedLocks {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl KeyedLocks {
+    /// Waits until no other request holds `key`, and holds it until the guard is dropped.
+    pub async fn lock(&self, key: &str) -> KeyGuard<'_> {
+        let lock = self.locks.lock().unwrap().entry(key.to_string()).or_default().clone();
+        KeyGuard { locks: self, key: key.to_string(), guard: Some(lock.lock_owned().await) }
+    }
+}
+
+pub struct KeyGuard<'a> {
+    locks: &'a KeyedLocks,
+    key: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for KeyGuard<'_> {
+    fn drop(&mut self) {
+        let mut locks = self.locks.locks.lock().unwrap();
+        drop(self.guard.take());
+        if locks.get(&self.key).is_some_and(|lock| Arc::strong_count(lock) == 1) {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+fn window() -> Duration {
+    Duration::from_secs(
+        env::var("DEDUPE_WINDOW_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_DEDUPE_WINDOW_SECS),
+    )
+}
+
+pub enum Claim {
+    /// The place was added successfully within the dedupe window, with this message.
+    Duplicate(String),
+    /// This request adds the place; duplicates wait until it is dropped.
+    Claimed(InFlight),
+}
+
+/// Marks `place_id` as being added. A duplicate arriving while the first add is still
+/// running waits for it, then gets its result if it succeeded or takes over if it failed.
+pub async fn claim(place_id: &str) -> Claim {
+    let window = window();
+    if window.is_zero() {
+        return Claim::Claimed(InFlight { place_id: place_id.to_string(), guard: None });
+    }
+
+    let in_flight = InFlight { place_id: place_id.to_string(), guard: Some(IN_FLIGHT.lock(place_id).await) };
+
+    let mut results = RECENT_RESULTS.lock().unwrap();
+    results.retain(|_, (finished, _)| finished.elapsed() < window);
+    let recent = results.get(place_id);
+    crate::metrics::record_dedupe_lookup(recent.is_some());
+    match recent {
+        Some((_, message)) => {
+            info!("Suppressing rapid duplicate add of place {}", place_id);
+            Claim::Duplicate(message.clone())
+        }
+        None => Claim::Claimed(in_flight),
+    }
+}
+
+pub struct InFlight {
+    place_id: String,
+    guard: Option<KeyGuard<'static>>,
+}
+
+impl InFlight {
+    /// Remembers a successful add; failures aren't, so a retry tries again.
+    pub fn succeeded(&self, message: &str) {
+        if self.guard.is_some() {
+            RECENT_RESULTS.lock().unwrap().insert(self.place_id.clone(), (Instant::now(), message.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Looks a page up and creates it when missing, with a round trip in between as with Notion.
+    async fn write_page(locks: &KeyedLocks, pages: &Mutex<Vec<String>>, place_id: &str) {
+        let _guard = locks.lock(place_id).await;
+        let exists = pages.lock().unwrap().iter().any(|page| page == place_id);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        if !exists {
+            pages.lock().unwrap().push(place_id.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn simultaneous_writes_for_one_place_create_one_page() {
+        let locks = KeyedLocks::default();
+        let pages = Mutex::new(Vec::new());
+        tokio::join!(write_page(&locks, &pages, "ChIJabc"), write_page(&locks, &pages, "ChIJabc"));
+        assert_eq!(*pages.lock().unwrap(), vec!["ChIJabc".to_string()]);
+        assert!(locks.locks.lock().unwrap().is_empty());
+    }
+}
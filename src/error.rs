@@ -0,0 +1,98 @@
+use actix_web::http::StatusCode;
+use std::env;
+use std::fmt;
+
+/// What went wrong while adding a restaurant, so the HTTP layer can pick a status
+/// (400 for validation, 502 for upstream API failures, 503 when Google's quota is used
+/// up, 500 otherwise).
+#[derive(Debug)]
+pub enum GastropathError {
+    /// The input can't be used as is (bad link, shared list).
+    Validation(String),
+    /// The resolved name looks like an address or a placeholder rather than a restaurant.
+    SuspiciousName(String),
+    /// A text search matched several places without a clear winner.
+    Ambiguous(String),
+    /// Google has no place matching the input.
+    NotFound(String),
+    /// The short link could not be followed to Google Maps.
+    Expansion(String),
+    GooglePlaces(String),
+    /// Google's quota is used up, for the key in use or for all of them.
+    QuotaExceeded(String),
+    /// A photo is below the minimum size, so another one should be tried.
+    PhotoTooSmall(String),
+    Yelp(String),
+    Cloudinary(String),
+    Notion(String),
+    Http(reqwest::Error),
+    Other(String),
+}
+
+impl fmt::Display for GastropathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GastropathError::Validation(message)
+            | GastropathError::SuspiciousName(message)
+            | GastropathError::Ambiguous(message)
+            | GastropathError::NotFound(message)
+            | GastropathError::Expansion(message)
+            | GastropathError::GooglePlaces(message)
+            | GastropathError::QuotaExceeded(message)
+            | GastropathError::PhotoTooSmall(message)
+            | GastropathError::Yelp(message)
+            | GastropathError::Cloudinary(message)
+            | GastropathError::Notion(message)
+            | GastropathError::Other(message) => write!(f, "{}", message),
+            GastropathError::Http(e) => write!(f, "HTTP request failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GastropathError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GastropathError::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for GastropathError {
+    fn from(e: reqwest::Error) -> Self {
+        GastropathError::Http(e)
+    }
+}
+
+impl GastropathError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            GastropathError::Validation(_) | GastropathError::SuspiciousName(_) | GastropathError::Ambiguous(_) => {
+                error_status("VALIDATION_ERROR_STATUS")
+            }
+            GastropathError::NotFound(_) => error_status("NOT_FOUND_STATUS"),
+            GastropathError::QuotaExceeded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            // A response that doesn't parse is a bug on this side as much as upstream's
+            GastropathError::Http(e) if e.is_decode() => StatusCode::INTERNAL_SERVER_ERROR,
+            GastropathError::Expansion(_)
+            | GastropathError::GooglePlaces(_)
+            | GastropathError::PhotoTooSmall(_)
+            | GastropathError::Yelp(_)
+            | GastropathError::Cloudinary(_)
+            | GastropathError::Notion(_)
+            | GastropathError::Http(_) => StatusCode::BAD_GATEWAY,
+            GastropathError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Status for a class of client errors, from `VALIDATION_ERROR_STATUS` or
+/// `NOT_FOUND_STATUS`. Only 4xx codes are accepted; the default is 400.
+pub fn error_status(variable: &str) -> StatusCode {
+    env::var(variable)
+        .ok()
+        .and_then(|v| v.parse::<u16>().ok())
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .filter(|status| status.is_client_error())
+        .unwrap_or(StatusCode::BAD_REQUEST)
+}
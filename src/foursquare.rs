@@ -0,0 +1,75 @@
+use reqwest::Client;
+use serde_json::Value;
+use std::env;
+use crate::rate_limit::TrackedSend;
+use crate::error::GastropathError;
+use crate::utils::{Provider, RequestBuilderExt};
+use log::{info, debug, warn};
+
+const DEFAULT_MIN_NAME_SIMILARITY: f64 = 0.5;
+
+/// Looks the restaurant up on Foursquare and returns its category names, or "❓"
+/// when there is no confident match.
+pub async fn get_cuisine_type(client: &Client, restaurant_name: &str, city: &str, coordinates: Option<(f64, f64)>, request_id: &str) -> Result<String, GastropathError> {
+    info!("Getting Foursquare categories for {} in {}", restaurant_name, city);
+    let api_key = env::var("FOURSQUARE_API_KEY").map_err(|_| GastropathError::Other("FOURSQUARE_API_KEY must be set".to_string()))?;
+    let url = format!("{}/v3/places/search", crate::utils::api_base(Provider::Foursquare));
+
+    let mut params = vec![
+        ("query", restaurant_name.to_string()),
+        ("limit", "1".to_string()),
+    ];
+    match coordinates {
+        Some((lat, lng)) => params.push(("ll", format!("{},{}", lat, lng))),
+        None => params.push(("near", city.to_string())),
+    }
+
+    debug!("Sending request to Foursquare API with params: {:?}", params);
+
+    let response = client.get(url)
+        .provider_timeout("FOURSQUARE")
+        .query(&params)
+        .header("Authorization", api_key)
+        .header("Accept", "application/json")
+        .send_tracked("FOURSQUARE")
+        .await?
+        .json::<Value>()
+        .await?;
+
+    crate::logging::dump_raw_response(request_id, "foursquare_search", &response);
+
+    if let Some(message) = response["message"].as_str() {
+        warn!("Foursquare API error: {}", message);
+        return Ok("❓".to_string());
+    }
+
+    if let Some(place) = response["results"].as_array().and_then(|results| results.first()) {
+        let place_name = place["name"].as_str().unwrap_or_default();
+        let similarity = crate::utils::name_similarity(restaurant_name, place_name);
+        let min_similarity = env::var("FOURSQUARE_MIN_NAME_SIMILARITY")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(DEFAULT_MIN_NAME_SIMILARITY);
+        if similarity < min_similarity {
+            warn!(
+                "Rejected Foursquare match '{}' for {} (similarity {:.2} < {:.2})",
+                place_name, restaurant_name, similarity, min_similarity
+            );
+            return Ok("❓".to_string());
+        }
+
+        let categories: Vec<&str> = place["categories"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|category| category["name"].as_str())
+            .collect();
+        if !categories.is_empty() {
+            info!("Found Foursquare categories: {:?}", categories);
+            return Ok(categories.join(", "));
+        }
+    }
+
+    warn!("No Foursquare categories found for {} in {}", restaurant_name, city);
+    Ok("❓".to_string())
+}
@@ -1,10 +1,31 @@
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
-use log::{info, error, debug};
+use crate::utils::{Provider, RequestBuilderExt};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+use crate::error::GastropathError;
+use crate::timing::StageTimings;
+use log::{info, error, debug, warn};
+
+const DEFAULT_NEARBY_SEARCH_RADIUS: u32 = 50;
+const DEFAULT_GOOGLE_MAX_RETRIES: u32 = 3;
+const DEFAULT_MAX_PHOTOS: usize = 1;
+/// Candidates at least this similar to the searched name count as plausible matches.
+const AMBIGUOUS_MIN_NAME_SIMILARITY: f64 = 0.5;
+const DETAILS_FIELDS: &str = "place_id,name,formatted_address,website,price_level,address_component,photos,url,geometry,types,vicinity,utc_offset,formatted_phone_number,international_phone_number,opening_hours";
+
+lazy_static::lazy_static! {
+    /// Google API keys that hit their quota, with the (UTC) day they were exhausted on.
+    static ref EXHAUSTED_KEYS: Mutex<HashMap<String, chrono::NaiveDate>> = Mutex::new(HashMap::new());
+}
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PlaceDetails {
+    pub place_id: Option<String>,
     pub name: String,
     pub website: String,
     pub price_level: String,
@@ -12,132 +33,765 @@ pub struct PlaceDetails {
     pub country: String,
     pub google_maps_link: String,
     pub address: String,
-    pub photo_reference: Option<String>,
+    pub coordinates: Option<(f64, f64)>,
+    pub types: Vec<String>,
+    pub neighborhood: Option<String>,
+    /// The photos to store, cover first, see [`select_photos`].
+    pub photos: Vec<Photo>,
+    /// Number of photos Google returned (at most 10).
+    pub photo_count: usize,
+    pub rating: Option<f64>,
+    pub rating_count: Option<u64>,
+    pub phone: Option<String>,
+    /// Google's `weekday_text` lines, e.g. `Monday: 12:00 – 10:00 PM`. Usually seven,
+    /// a single line for places open 24 hours, none when unknown.
+    pub opening_hours: Vec<String>,
+    /// ISO 3166-1 alpha-2 code of the country, e.g. `FR`.
+    pub country_code: Option<String>,
+    /// Current offset from UTC in minutes.
+    pub utc_offset: Option<i64>,
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Photo {
+    pub reference: String,
+    pub width: u64,
+    pub height: u64,
+    pub attribution: Option<PhotoAttribution>,
+}
+
+/// Contributor credit Google requires to be shown alongside its photos.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PhotoAttribution {
+    pub name: String,
+    pub link: Option<String>,
 }
 
-pub async fn get_place_details(client: &Client, identifier: &str) -> Result<PlaceDetails, Box<dyn std::error::Error>> {
+#[derive(Debug, Default)]
+struct PlaceInfo {
+    id: Option<String>,
+    query: String,
+    coordinates: Option<(f64, f64)>,
+    /// Only coordinates were shared (a dropped pin), there is no place to look up by text.
+    dropped_pin: bool,
+    /// A Google place_id known up front, e.g. one stored in Notion.
+    place_id: Option<String>,
+    /// Google's customer id (`cid`/`ludocid`, or the second half of an `lrd` feature id).
+    cid: Option<String>,
+    /// A Plus Code, global (`849VCWC8+R9`) or local with a locality (`CWC8+R9 Mountain View`).
+    plus_code: Option<String>,
+}
+
+pub async fn get_place_details(
+    client: &Client,
+    identifier: &str,
+    request_id: &str,
+    timings: &mut StageTimings,
+) -> Result<PlaceDetails, GastropathError> {
     info!("Getting place details for: {}", identifier);
-    let api_key = env::var("GOOGLE_API_KEY")?;
-    let (ftid, query) = if identifier.starts_with("http") {
-        let expanded_url = crate::utils::expand_short_url(identifier).await?;
-        extract_place_info(&expanded_url)?
+    let place_info = if identifier.starts_with("https://maps.apple.com") {
+        extract_apple_place_info(identifier)?
+    } else if let Some(place_id) = identifier.strip_prefix("place_id:") {
+        PlaceInfo { place_id: Some(place_id.to_string()), ..Default::default() }
+    } else if let Some(cid) = identifier.strip_prefix("cid:") {
+        PlaceInfo { cid: Some(cid.to_string()), ..Default::default() }
+    } else if url::Url::parse(identifier).ok().and_then(|url| url.host_str().map(crate::utils::is_full_maps_host)) == Some(true) {
+        extract_place_info(identifier)?
+    } else if identifier.starts_with("http") {
+        let stage_started = Instant::now();
+        let expanded_url = crate::utils::expand_short_url(client, identifier).await;
+        timings.record("expand", stage_started);
+        extract_place_info(&expanded_url?)?
     } else {
-        (None, identifier.to_string())
+        PlaceInfo { query: identifier.to_string(), plus_code: is_plus_code(identifier).then(|| identifier.to_string()), ..Default::default() }
     };
 
-    debug!("Extracted ID: {:?}, Query: {}", ftid, query);
+    debug!("Extracted place info: {:?}", place_info);
 
-    let details = if let Some(id) = ftid {
-        match get_details_by_ftid(client, &api_key, id).await {
-            Ok(details) => details,
-            Err(e) => {
-                error!("Error getting place details by FTID: {:?}", e);
-                return Err(e);
+    let stage_started = Instant::now();
+    let mut details = loop {
+        let api_key = current_api_key()?;
+        let result = if let Some(place_id) = &place_info.place_id {
+            get_details_by_place_id(client, &api_key, place_id, request_id).await
+        } else if let Some(id) = &place_info.id {
+            get_details_by_identifier(client, &api_key, "ftid", id, request_id).await
+        } else if let Some(cid) = &place_info.cid {
+            get_details_by_identifier(client, &api_key, "cid", cid, request_id).await
+        } else if let Some(plus_code) = &place_info.plus_code {
+            get_details_by_plus_code(client, &api_key, plus_code, request_id).await
+        } else if let (true, Some(coordinates)) = (place_info.dropped_pin, place_info.coordinates) {
+            get_details_by_nearby_search(client, &api_key, coordinates, request_id).await
+        } else {
+            get_details_by_query(client, &api_key, place_info.query.clone(), place_info.coordinates, request_id).await
+        };
+
+        match result {
+            Ok(details) => break details,
+            Err(GastropathError::QuotaExceeded(_)) => {
+                mark_key_exhausted(&api_key);
             }
-        }
-    } else {
-        match get_details_by_query(client, &api_key, query).await {
-            Ok(details) => details,
             Err(e) => {
-                error!("Error getting place details by query: {:?}", e);
+                error!("Error getting place details: {:?}", e);
                 return Err(e);
             }
         }
     };
+    timings.record("place_details", stage_started);
 
     if details.name == "Unknown" {
         error!("Failed to retrieve place details: Unknown place");
-        return Err("Place details not found: Unknown place".into());
+        return Err(GastropathError::NotFound("Place not found: Unknown place".to_string()));
+    }
+
+    if details.coordinates.is_none() {
+        details.coordinates = place_info.coordinates;
+    }
+
+    if env::var("NOTION_TIMEZONE_PROPERTY").map(|p| !p.is_empty()).unwrap_or(false) {
+        let stage_started = Instant::now();
+        details.timezone = resolve_timezone(client, &details, request_id).await;
+        timings.record("timezone", stage_started);
     }
 
     debug!("Retrieved place details: {:?}", details);
     Ok(details)
 }
 
-fn extract_place_info(url: &str) -> Result<(Option<String>, String), Box<dyn std::error::Error>> {
-    let parsed_url = url::Url::parse(url)?;
+fn configured_api_keys() -> Vec<String> {
+    let keys: Vec<String> = env::var("GOOGLE_API_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+        .collect();
+
+    if keys.is_empty() {
+        env::var("GOOGLE_API_KEY").into_iter().collect()
+    } else {
+        keys
+    }
+}
+
+/// Returns the first configured Google API key that has not been exhausted today.
+pub fn current_api_key() -> Result<String, GastropathError> {
+    let keys = configured_api_keys();
+    if keys.is_empty() {
+        return Err(GastropathError::Other("GOOGLE_API_KEY or GOOGLE_API_KEYS must be set".to_string()));
+    }
+
+    let today = chrono::Utc::now().date_naive();
+    let mut exhausted = EXHAUSTED_KEYS.lock().unwrap();
+    exhausted.retain(|_, day| *day == today);
+
+    keys.into_iter()
+        .find(|key| !exhausted.contains_key(key))
+        .ok_or_else(|| GastropathError::QuotaExceeded("All Google API keys are exhausted for today".to_string()))
+}
+
+fn mark_key_exhausted(key: &str) {
+    warn!("Google API key {} exhausted for today, rotating to the next key", crate::utils::mask_api_key(key));
+    EXHAUSTED_KEYS.lock().unwrap().insert(key.to_string(), chrono::Utc::now().date_naive());
+}
+
+/// URL of a Google place photo, signed with the current API key.
+/// The requested size is `GOOGLE_PHOTO_MAX_WIDTH` (default 800) and, when set,
+/// `GOOGLE_PHOTO_MAX_HEIGHT`; Google scales the photo down to fit both.
+pub fn photo_url(reference: &str) -> Result<String, GastropathError> {
+    let api_key = current_api_key()?;
+    let max_width = env::var("GOOGLE_PHOTO_MAX_WIDTH").ok().and_then(|v| v.parse::<u32>().ok()).unwrap_or(800);
+    let max_height = env::var("GOOGLE_PHOTO_MAX_HEIGHT")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .map(|height| format!("&maxheight={}", height))
+        .unwrap_or_default();
+    Ok(format!(
+        "{}/maps/api/place/photo?maxwidth={}{}&photoreference={}&key={}",
+        crate::utils::api_base(Provider::Google), max_width, max_height, encode(reference), api_key
+    ))
+}
+
+/// Fails with [`GastropathError::PhotoTooSmall`] when a dimension is below
+/// `PHOTO_MIN_WIDTH`/`PHOTO_MIN_HEIGHT`.
+pub fn check_photo_size(width: u64, height: u64) -> Result<(), GastropathError> {
+    for (actual, var) in [(width, "PHOTO_MIN_WIDTH"), (height, "PHOTO_MIN_HEIGHT")] {
+        if let Some(minimum) = env::var(var).ok().and_then(|v| v.parse::<u64>().ok()) {
+            if actual < minimum {
+                return Err(GastropathError::PhotoTooSmall(format!("Photo too small: {}x{} is below {} {}", width, height, var, minimum)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Width and height from a PNG or JPEG header, `None` for other formats.
+pub fn image_dimensions(bytes: &[u8]) -> Option<(u64, u64)> {
+    let be_u16 = |at: usize| Some(u16::from_be_bytes([*bytes.get(at)?, *bytes.get(at + 1)?]) as u64);
+    let be_u32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as u64);
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some((be_u32(16)?, be_u32(20)?));
+    }
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    // Walk the JPEG segments up to the start-of-frame, which holds the size
+    let mut at = 2;
+    while *bytes.get(at)? == 0xFF {
+        let marker = *bytes.get(at + 1)?;
+        if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+            return Some((be_u16(at + 7)?, be_u16(at + 5)?));
+        }
+        at += 2 + be_u16(at + 2)? as usize;
+    }
+    None
+}
+
+/// Downloads a place photo, returning its bytes and content type.
+pub async fn download_photo(client: &Client, reference: &str) -> Result<(Vec<u8>, String), GastropathError> {
+    let response = client.get(photo_url(reference)?).provider_timeout("GOOGLE").send().await?;
+    if !response.status().is_success() {
+        return Err(GastropathError::GooglePlaces(format!("Google photo download failed with status {}", response.status())));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("image/jpeg")
+        .to_string();
+    let bytes = response.bytes().await?.to_vec();
+    if let Some((width, height)) = image_dimensions(&bytes) {
+        check_photo_size(width, height)?;
+    }
+    Ok((bytes, content_type))
+}
+
+fn check_quota(response: &serde_json::Value) -> Result<(), GastropathError> {
+    let status = response["status"].as_str().unwrap_or_default();
+    let error_message = response["error_message"].as_str().unwrap_or_default();
+    if status == "OVER_QUERY_LIMIT"
+        || (status == "REQUEST_DENIED" && error_message.to_lowercase().contains("billing"))
+    {
+        return Err(GastropathError::QuotaExceeded(format!("Google API quota exceeded: {} {}", status, error_message)));
+    }
+    Ok(())
+}
+
+fn extract_place_info(url: &str) -> Result<PlaceInfo, GastropathError> {
+    let parsed_url = parse_url(url)?;
+    if is_shared_list(&parsed_url) {
+        warn!("Link resolves to a shared list: {}", url);
+        return Err(GastropathError::Validation(format!(
+            "Link is a shared list: {}. Lists can't be added as a whole, please share each place's link instead",
+            url
+        )));
+    }
     let query_params: std::collections::HashMap<_, _> = parsed_url.query_pairs().into_owned().collect();
 
+    let data_id = data_place_id(parsed_url.path());
     let id = query_params.get("ftid")
         .or_else(|| query_params.get("place_id"))
-        .cloned();
-
-    let query = query_params.get("q")
         .cloned()
-        .unwrap_or_else(|| url.to_string());
+        .or_else(|| data_id.clone().filter(|id| id.starts_with("0x")));
+
+    // `q=place_id:ChIJ...` (as in canonical links) and `query_place_id=` name the place directly
+    let place_id = query_params
+        .get("q")
+        .and_then(|q| q.trim().strip_prefix("place_id:"))
+        .or_else(|| query_params.get("query_place_id").map(String::as_str))
+        .map(str::trim)
+        .filter(|place_id| !place_id.is_empty())
+        .map(String::from)
+        .or_else(|| data_id.filter(|id| id.starts_with("ChIJ")));
+    if place_id.is_some() {
+        debug!("Extracted place_id from URL: {:?}", place_id);
+        return Ok(PlaceInfo { place_id, query: url.to_string(), ..Default::default() });
+    }
+
+    let cid = query_params
+        .get("cid")
+        .or_else(|| query_params.get("ludocid"))
+        .map(|cid| cid.trim().to_string())
+        .filter(|cid| !cid.is_empty() && cid.chars().all(|c| c.is_ascii_digit()))
+        .or_else(|| query_params.get("lrd").and_then(|lrd| cid_from_feature_id(lrd)));
+
+    let q_coordinates = query_params.get("q").and_then(|q| parse_lat_lng(q));
+    let coordinates = q_coordinates.or_else(|| extract_coordinates(parsed_url.path()));
+
+    // Search links (`/maps/search/pizza+near+me`, `?api=1&query=...`) only give a term,
+    // which goes through the text search like any name, ambiguity checks included
+    let name = match query_params.get("q") {
+        Some(q) if q_coordinates.is_none() => Some(q.clone()),
+        Some(_) => None,
+        None => place_name_from_path(parsed_url.path()).or_else(|| query_params.get("query").cloned()),
+    };
+    if parsed_url.path().contains("/search") {
+        info!("Link is a Maps search, looking up its term: {:?}", name);
+    }
+    let dropped_pin = id.is_none() && cid.is_none() && coordinates.is_some() && name.is_none();
+    let plus_code = name.as_deref().filter(|name| is_plus_code(name)).map(String::from);
+    let query = name.unwrap_or_else(|| url.to_string());
+
+    debug!("Extracted parameters - id: {:?}, cid: {:?}, query: {}, coordinates: {:?}, dropped pin: {}, plus code: {:?}", id, cid, query, coordinates, dropped_pin, plus_code);
+    Ok(PlaceInfo { id, query, coordinates, dropped_pin, place_id: None, cid, plus_code })
+}
+
+/// The `!1s` element of a full link's `/data=` path segment: a feature id like
+/// `0x47e66e2964e34e2d:0x8ddca9ee380ef7e0`, or occasionally a place_id.
+fn data_place_id(path: &str) -> Option<String> {
+    let data = path.split('/').find_map(|segment| segment.strip_prefix("data="))?;
+    let data = data.replace("%21", "!").replace("%3A", ":").replace("%3a", ":");
+    data.split('!')
+        .find_map(|element| element.strip_prefix("1s"))
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+}
+
+/// The decimal cid in a feature id like `0x47e66e2964e34e2d:0x8ddca9ee380ef7e0` (as in
+/// `lrd`, possibly followed by `,1,,,`), whose second half is the cid in hex.
+fn cid_from_feature_id(feature_id: &str) -> Option<String> {
+    let (_, cid) = feature_id.split(',').next()?.split_once(':')?;
+    let cid = cid.trim().strip_prefix("0x")?;
+    u64::from_str_radix(cid, 16).ok().map(|cid| cid.to_string())
+}
+
+/// Shared saved lists expand to `/maps/placelists/list/<id>`, or to a map view whose
+/// `data=` carries a `!11m` list block (`.../data=!4m3!11m2!2s<id>!3e3`).
+fn is_shared_list(url: &url::Url) -> bool {
+    let path = url.path();
+    path.contains("/placelists/") || (!path.contains("/place/") && path.contains("!11m") && path.contains("!2s"))
+}
+
+/// Returns the `<name>` of a `/maps/place/<name>/...` path, or the search term of a
+/// `/maps/search/<term>/...` one, unless it is itself a coordinate pair (as in dropped-pin
+/// links like `/maps/place/48°51'24.0"N+2°21'08.0"E`).
+fn place_name_from_path(path: &str) -> Option<String> {
+    let mut segments = path.split('/');
+    segments.find(|segment| *segment == "place" || *segment == "search")?;
+    let segment = segments.next().filter(|s| !s.is_empty() && !s.starts_with('@'))?;
+    let name = url::form_urlencoded::parse(format!("n={}", segment).as_bytes())
+        .next()
+        .map(|(_, value)| value.into_owned())?;
+
+    if name.contains('°') || parse_lat_lng(&name).is_some() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Apple Maps links carry the place name (`q` or `name`), sometimes an address, and
+/// usually coordinates (`ll`, `coordinate` or `sll`), which bias the Google text search.
+fn extract_apple_place_info(url: &str) -> Result<PlaceInfo, GastropathError> {
+    let parsed_url = parse_url(url)?;
+    let query_params: HashMap<_, _> = parsed_url.query_pairs().into_owned().collect();
+
+    let name = query_params.get("q")
+        .or_else(|| query_params.get("name"))
+        .ok_or_else(|| GastropathError::Validation("Apple Maps URL has no place name".to_string()))?;
+    let query = match query_params.get("address") {
+        Some(address) => format!("{}, {}", name, address),
+        None => name.clone(),
+    };
+
+    let coordinates = ["ll", "coordinate", "sll"]
+        .iter()
+        .filter_map(|key| query_params.get(*key))
+        .find_map(|value| parse_lat_lng(value));
+
+    debug!("Extracted Apple Maps parameters - query: {}, coordinates: {:?}", query, coordinates);
+    Ok(PlaceInfo { query, coordinates, ..Default::default() })
+}
+
+/// Parses the `@lat,lng,zoom` segment that full Maps URLs embed in their path,
+/// e.g. `/maps/place/Foo/@48.8566,2.3522,17z/data=...`.
+fn extract_coordinates(path: &str) -> Option<(f64, f64)> {
+    path.split('/').find_map(|segment| parse_lat_lng(segment.strip_prefix('@')?))
+}
+
+fn parse_url(url: &str) -> Result<url::Url, GastropathError> {
+    url::Url::parse(url).map_err(|e| GastropathError::Validation(format!("Invalid URL {}: {}", url, e)))
+}
+
+/// Parses `lat,lng` (extra comma-separated parts such as a zoom level are ignored).
+fn parse_lat_lng(text: &str) -> Option<(f64, f64)> {
+    let mut parts = text.split(',');
+    let lat = parts.next()?.trim().parse::<f64>().ok()?;
+    let lng = parts.next()?.trim().parse::<f64>().ok()?;
+    ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng)).then_some((lat, lng))
+}
+
+/// Percent-encodes a value for a Google API query string, so names like
+/// `Bob & Joe's #1` stay a single parameter.
+fn encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+/// GETs a Google API URL and parses the JSON, retrying network errors and HTTP 429/5xx
+/// up to `GOOGLE_MAX_RETRIES` times (default 3) with backoff from 200ms, doubling each time.
+/// Other statuses are not retried; Google reports its errors in the body.
+async fn google_get(client: &Client, url: &str) -> Result<serde_json::Value, GastropathError> {
+    let max_retries = env::var("GOOGLE_MAX_RETRIES")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_GOOGLE_MAX_RETRIES);
+
+    let mut attempt = 0;
+    loop {
+        let error = match client.get(url).provider_timeout("GOOGLE").send().await {
+            Ok(response) if response.status().as_u16() == 429 || response.status().is_server_error() => {
+                GastropathError::GooglePlaces(format!("Google returned HTTP {}", response.status()))
+            }
+            Ok(response) => return Ok(response.json::<serde_json::Value>().await?),
+            Err(e) => GastropathError::Http(e),
+        };
+
+        if attempt >= max_retries {
+            return Err(error);
+        }
+        let backoff = Duration::from_millis(200 << attempt.min(6));
+        warn!("Google request failed ({}), retry {} of {} in {}ms", error, attempt + 1, max_retries, backoff.as_millis());
+        tokio::time::sleep(backoff).await;
+        attempt += 1;
+    }
+}
 
-    debug!("Extracted parameters - id: {:?}, query: {}", id, query);
-    Ok((id, query))
+/// `&language=` from `GOOGLE_PLACES_LANGUAGE` (default `en`) and `&region=` from
+/// `GOOGLE_PLACES_REGION`, each left out when empty.
+fn locale_params() -> String {
+    let language = env::var("GOOGLE_PLACES_LANGUAGE").unwrap_or_else(|_| "en".to_string());
+    let region = env::var("GOOGLE_PLACES_REGION").unwrap_or_default();
+    [("language", language), ("region", region)]
+        .iter()
+        .filter(|(_, value)| !value.trim().is_empty())
+        .map(|(param, value)| format!("&{}={}", param, encode(value.trim())))
+        .collect()
 }
 
-async fn get_details_by_ftid(client: &Client, api_key: &str, ftid: String) -> Result<PlaceDetails, Box<dyn std::error::Error>> {
+/// Details lookup by a Google identifier other than the place_id: `ftid` or `cid`.
+async fn get_details_by_identifier(client: &Client, api_key: &str, param: &str, value: &str, request_id: &str) -> Result<PlaceDetails, GastropathError> {
     let url = format!(
-        "https://maps.googleapis.com/maps/api/place/details/json?ftid={}&fields=name,formatted_address,website,price_level,address_component,photos,url&key={}",
-        ftid, api_key
+        "{}/maps/api/place/details/json?{}={}&fields={}{}&key={}",
+        crate::utils::api_base(Provider::Google), param, encode(value), details_fields(), locale_params(), api_key
     );
     debug!("Requesting place details with URL: {}", url);
-    let response = client.get(&url).send().await?.json::<serde_json::Value>().await?;
+    let response = google_get(client, &url).await?;
+    crate::logging::dump_raw_response(request_id, "google_details", &response);
+    check_quota(&response)?;
+    if let Some("NOT_FOUND" | "ZERO_RESULTS" | "INVALID_REQUEST") = response["status"].as_str() {
+        return Err(GastropathError::NotFound(format!("Place not found: Google has no place with {} {}", param, value)));
+    }
     if let Some(error_message) = response["error_message"].as_str() {
         error!("Google Places API error: {}. Full response: {:?}", error_message, response);
-        return Err(format!("Google Places API error: {}. Full response: {:?}", error_message, response).into());
+        return Err(GastropathError::GooglePlaces(format!("Google Places API error: {}. Full response: {:?}", error_message, response)));
     }
     process_place_details(&response["result"])
 }
 
-async fn get_details_by_query(client: &Client, api_key: &str, query: String) -> Result<PlaceDetails, Box<dyn std::error::Error>> {
-    let find_place_url = format!(
-        "https://maps.googleapis.com/maps/api/place/findplacefromtext/json?input={}&inputtype=textquery&fields=place_id&key={}",
-        query, api_key
+async fn get_details_by_query(client: &Client, api_key: &str, query: String, coordinates: Option<(f64, f64)>, request_id: &str) -> Result<PlaceDetails, GastropathError> {
+    let find_place_url = find_place_url(api_key, &query, coordinates);
+    let find_place_response = google_get(client, &find_place_url).await?;
+    crate::logging::dump_raw_response(request_id, "google_find_place", &find_place_response);
+    check_quota(&find_place_response)?;
+    if let Some(error_message) = find_place_response["error_message"].as_str() {
+        error!("Google Places API error: {}", error_message);
+        return Err(GastropathError::GooglePlaces(format!("Google Places API error: {}", error_message)));
+    }
+    let place_id = match find_place_response["status"].as_str() {
+        Some("OK") => pick_candidate(&query, find_place_response["candidates"].as_array().map(Vec::as_slice).unwrap_or_default())?,
+        Some("ZERO_RESULTS") => return Err(GastropathError::NotFound(format!("Place not found: nothing matches '{}'", query))),
+        status => {
+            return Err(GastropathError::GooglePlaces(format!("Google Places search failed with status {}", status.unwrap_or("unknown"))))
+        }
+    };
+
+    get_details_by_place_id(client, api_key, &place_id, request_id).await
+}
+
+fn find_place_url(api_key: &str, query: &str, coordinates: Option<(f64, f64)>) -> String {
+    let mut url = format!(
+        "{}/maps/api/place/findplacefromtext/json?input={}&inputtype=textquery&fields=place_id,name,formatted_address{}&key={}",
+        crate::utils::api_base(Provider::Google), encode(query), locale_params(), api_key
     );
+    if let Some((lat, lng)) = coordinates {
+        url.push_str(&format!("&locationbias=point:{},{}", lat, lng));
+    }
+    url
+}
 
-    let find_place_response = client.get(&find_place_url).send().await?.json::<serde_json::Value>().await?;
-    if let Some(error_message) = find_place_response["error_message"].as_str() {
+/// Picks the candidate to use from a text search. When several candidates plausibly
+/// match the searched name, or the best match is below `RESOLUTION_CONFIDENCE`, guessing
+/// would often be wrong, so it fails with [`ambiguous`] listing the candidates to confirm from.
+fn pick_candidate(query: &str, candidates: &[serde_json::Value]) -> Result<String, GastropathError> {
+    let searched_name = query.split(',').next().unwrap_or(query);
+    let similarity = |c: &serde_json::Value| crate::utils::name_similarity(searched_name, c["name"].as_str().unwrap_or_default());
+    let mut chosen = candidates.first();
+    if candidates.len() > 1 {
+        let plausible: Vec<&serde_json::Value> = candidates.iter().filter(|c| similarity(c) >= AMBIGUOUS_MIN_NAME_SIMILARITY).collect();
+        if plausible.len() > 1 {
+            return Err(ambiguous(plausible.into_iter().map(candidate_label).collect()));
+        }
+        chosen = plausible.first().copied().or(chosen);
+    }
+
+    if let (Some(threshold), Some(candidate)) = (resolution_confidence(), chosen) {
+        let confidence = similarity(candidate);
+        if confidence < threshold {
+            info!("Best match for '{}' has confidence {:.2} < {:.2}, asking for confirmation", searched_name, confidence, threshold);
+            return Err(ambiguous(candidates.iter().take(5).map(candidate_label).collect()));
+        }
+    }
+
+    chosen
+        .and_then(|c| c["place_id"].as_str())
+        .map(String::from)
+        .ok_or_else(|| GastropathError::NotFound("Place not found: no candidate has a place_id".to_string()))
+}
+
+fn ambiguous(candidates: Vec<String>) -> GastropathError {
+    GastropathError::Ambiguous(format!(
+        "Ambiguous place, please provide a more specific link or send one of the candidates' links. Candidates: {}",
+        candidates.join("; ")
+    ))
+}
+
+/// `RESOLUTION_CONFIDENCE`, the name similarity (0 to 1) a text search's best match needs
+/// to be used without confirmation. Unset (or 0) always uses it.
+fn resolution_confidence() -> Option<f64> {
+    env::var("RESOLUTION_CONFIDENCE").ok().and_then(|v| v.trim().parse::<f64>().ok()).filter(|t| *t > 0.0)
+}
+
+/// A candidate as listed for confirmation: name, address and a link that resolves to it.
+fn candidate_label(candidate: &serde_json::Value) -> String {
+    let label = format!("{} ({})", candidate["name"].as_str().unwrap_or("?"), candidate["formatted_address"].as_str().unwrap_or("no address"));
+    match candidate["place_id"].as_str() {
+        Some(place_id) => format!("{} https://www.google.com/maps/place/?q=place_id:{}", label, place_id),
+        None => label,
+    }
+}
+
+/// Finds the closest restaurant to a dropped pin, within `NEARBY_SEARCH_RADIUS` meters.
+async fn get_details_by_nearby_search(client: &Client, api_key: &str, (lat, lng): (f64, f64), request_id: &str) -> Result<PlaceDetails, GastropathError> {
+    let radius: u32 = env::var("NEARBY_SEARCH_RADIUS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_NEARBY_SEARCH_RADIUS);
+    info!("Link is a dropped pin, searching for a restaurant within {}m of {},{}", radius, lat, lng);
+
+    let nearby_url = format!(
+        "{}/maps/api/place/nearbysearch/json?location={},{}&radius={}&type=restaurant{}&key={}",
+        crate::utils::api_base(Provider::Google), lat, lng, radius, locale_params(), api_key
+    );
+
+    let nearby_response = google_get(client, &nearby_url).await?;
+    crate::logging::dump_raw_response(request_id, "google_nearby_search", &nearby_response);
+    check_quota(&nearby_response)?;
+    if let Some(error_message) = nearby_response["error_message"].as_str() {
         error!("Google Places API error: {}", error_message);
-        return Err(format!("Google Places API error: {}", error_message).into());
+        return Err(GastropathError::GooglePlaces(format!("Google Places API error: {}", error_message)));
     }
-    let place_id = find_place_response["candidates"][0]["place_id"].as_str().ok_or("No place_id found")?;
 
+    let place_id = nearest_place_id(&nearby_response["results"], (lat, lng))
+        .ok_or_else(|| GastropathError::NotFound(format!("Place not found: no restaurant at these coordinates ({},{})", lat, lng)))?;
+
+    get_details_by_place_id(client, api_key, place_id, request_id).await
+}
+
+/// The place_id of the search result closest to `origin`. Nearby search ranks results
+/// by prominence, so the first one isn't necessarily the one under the pin.
+fn nearest_place_id(results: &serde_json::Value, origin: (f64, f64)) -> Option<&str> {
+    results
+        .as_array()?
+        .iter()
+        .filter_map(|result| {
+            let location = &result["geometry"]["location"];
+            let position = location["lat"].as_f64().zip(location["lng"].as_f64())?;
+            Some((distance_meters(origin, position), result["place_id"].as_str()?))
+        })
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, place_id)| place_id)
+}
+
+/// Great-circle distance between two coordinates (haversine).
+fn distance_meters((lat1, lng1): (f64, f64), (lat2, lng2): (f64, f64)) -> f64 {
+    const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+    let (dlat, dlng) = ((lat2 - lat1).to_radians(), (lng2 - lng1).to_radians());
+    let a = (dlat / 2.0).sin().powi(2) + lat1.to_radians().cos() * lat2.to_radians().cos() * (dlng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+}
+
+/// Whether the text starts with a Plus Code: up to 8 code characters, a `+`, and 2 or
+/// 3 more, optionally followed by a locality.
+fn is_plus_code(text: &str) -> bool {
+    const ALPHABET: &str = "23456789CFGHJMPQRVWX";
+    let code = text.split_whitespace().next().unwrap_or_default().to_uppercase();
+    let (before, after) = match code.split_once('+') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let valid = |part: &str| part.chars().all(|c| ALPHABET.contains(c) || c == '0');
+    (2..=8).contains(&before.len()) && before.len() % 2 == 0 && (2..=3).contains(&after.len()) && valid(before) && valid(after)
+}
+
+/// Geocodes a Plus Code to coordinates, then takes the nearest restaurant there.
+async fn get_details_by_plus_code(client: &Client, api_key: &str, plus_code: &str, request_id: &str) -> Result<PlaceDetails, GastropathError> {
+    info!("Link is a Plus Code, geocoding {}", plus_code);
+    let geocode_url = format!(
+        "{}/maps/api/geocode/json?address={}{}&key={}",
+        crate::utils::api_base(Provider::Google), encode(plus_code),
+        locale_params(),
+        api_key
+    );
+
+    let response = google_get(client, &geocode_url).await?;
+    crate::logging::dump_raw_response(request_id, "google_geocode", &response);
+    check_quota(&response)?;
+    let location = &response["results"][0]["geometry"]["location"];
+    let coordinates = location["lat"]
+        .as_f64()
+        .zip(location["lng"].as_f64())
+        .ok_or_else(|| GastropathError::NotFound(format!("Place not found: Plus Code '{}' could not be resolved", plus_code)))?;
+
+    get_details_by_nearby_search(client, api_key, coordinates, request_id).await
+}
+
+/// Whether details requests include the rating fields, decided once at startup.
+static REQUEST_RATING_FIELDS: AtomicBool = AtomicBool::new(false);
+
+/// Requests the rating fields (billed as Atmosphere data) with every place's details,
+/// for when a rating is written to Notion.
+pub fn set_rating_fields(requested: bool) {
+    REQUEST_RATING_FIELDS.store(requested, Ordering::Relaxed);
+}
+
+/// `DETAILS_FIELDS`, plus the rating fields when [`set_rating_fields`] asked for them.
+fn details_fields() -> String {
+    if REQUEST_RATING_FIELDS.load(Ordering::Relaxed) {
+        format!("{},rating,user_ratings_total", DETAILS_FIELDS)
+    } else {
+        DETAILS_FIELDS.to_string()
+    }
+}
+
+async fn get_details_by_place_id(client: &Client, api_key: &str, place_id: &str, request_id: &str) -> Result<PlaceDetails, GastropathError> {
     let details_url = format!(
-        "https://maps.googleapis.com/maps/api/place/details/json?place_id={}&fields=name,formatted_address,website,price_level,address_component,photos,url&key={}",
-        place_id, api_key
+        "{}/maps/api/place/details/json?place_id={}&fields={}{}&key={}",
+        crate::utils::api_base(Provider::Google), encode(place_id), details_fields(), locale_params(), api_key
     );
 
-    let response = client.get(&details_url).send().await?.json::<serde_json::Value>().await?;
+    let response = google_get(client, &details_url).await?;
+    crate::logging::dump_raw_response(request_id, "google_details", &response);
+    check_quota(&response)?;
     if let Some(error_message) = response["error_message"].as_str() {
         error!("Google Places API error: {}", error_message);
-        return Err(format!("Google Places API error: {}", error_message).into());
+        return Err(GastropathError::GooglePlaces(format!("Google Places API error: {}", error_message)));
     }
     process_place_details(&response["result"])
 }
 
-fn process_place_details(details: &serde_json::Value) -> Result<PlaceDetails, Box<dyn std::error::Error>> {
+/// With `TIMEZONE_LOOKUP=true`, asks the Time Zone API for the zone name (e.g.
+/// `Europe/Paris`); otherwise, or when that fails, formats the details' UTC offset
+/// (`UTC+02:00`). `None` when neither is available.
+async fn resolve_timezone(client: &Client, details: &PlaceDetails, request_id: &str) -> Option<String> {
+    let lookup = env::var("TIMEZONE_LOOKUP").map(|v| v == "true" || v == "1").unwrap_or(false);
+    if let (true, Some(coordinates)) = (lookup, details.coordinates) {
+        match get_timezone_id(client, coordinates, request_id).await {
+            Ok(timezone) => return Some(timezone),
+            Err(e) => warn!("Failed to look up the timezone of {}: {}", details.name, e),
+        }
+    }
+    details.utc_offset.map(|offset| {
+        let sign = if offset < 0 { '-' } else { '+' };
+        format!("UTC{}{:02}:{:02}", sign, offset.abs() / 60, offset.abs() % 60)
+    })
+}
+
+async fn get_timezone_id(client: &Client, (lat, lng): (f64, f64), request_id: &str) -> Result<String, GastropathError> {
+    let url = format!(
+        "{}/maps/api/timezone/json?location={},{}&timestamp={}&key={}",
+        crate::utils::api_base(Provider::Google), lat, lng, chrono::Utc::now().timestamp(), current_api_key()?
+    );
+    let response = google_get(client, &url).await?;
+    crate::logging::dump_raw_response(request_id, "google_timezone", &response);
+    match response["status"].as_str() {
+        Some("OK") => response["timeZoneId"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| GastropathError::GooglePlaces("Time Zone API response has no timeZoneId".to_string())),
+        status => Err(GastropathError::GooglePlaces(format!("Time Zone API returned {}", status.unwrap_or("no status")))),
+    }
+}
+
+fn process_place_details(details: &serde_json::Value) -> Result<PlaceDetails, GastropathError> {
+    let place_id = details["place_id"].as_str().map(String::from);
     let name = details["name"].as_str().unwrap_or("Unknown").to_string();
     let website = details["website"].as_str().unwrap_or("No website available").to_string();
     let price_level = match details["price_level"].as_i64() {
-        Some(level) => "💵".repeat(level as usize),
-        None => "❓".to_string(),
+        Some(level) => format_price_level(level),
+        None => unknown_price(),
     };
     let address = details["formatted_address"].as_str().unwrap_or("No address available").to_string();
-    let google_maps_link = details["url"].as_str().unwrap_or("No link available").to_string();
-
-    let mut city = "No city available".to_string();
-    let mut country = "No country available".to_string();
-    if let Some(components) = details["address_components"].as_array() {
-        for component in components {
-            if let Some(types) = component["types"].as_array() {
-                if types.contains(&serde_json::json!("locality")) {
-                    city = component["long_name"].as_str().unwrap_or(&city).to_string();
-                }
-                if types.contains(&serde_json::json!("country")) {
-                    country = component["long_name"].as_str().unwrap_or(&country).to_string();
-                }
-            }
-        }
-    }
+    let google_maps_link = match (env::var("GOOGLE_MAPS_LINK_FORMAT").as_deref(), &place_id) {
+        (Ok("canonical"), Some(place_id)) => format!("https://www.google.com/maps/place/?q=place_id:{}", place_id),
+        _ => details["url"].as_str().unwrap_or("No link available").to_string(),
+    };
 
-    let photo_reference = details["photos"][0]["photo_reference"].as_str().map(String::from);
+    let components = details["address_components"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let city = city_component(components)
+        .unwrap_or_else(|| env::var("DEFAULT_CITY").unwrap_or_else(|_| "No city available".to_string()));
+    let country_code = components
+        .iter()
+        .find(|component| component["types"].as_array().is_some_and(|types| types.iter().any(|t| t == "country")))
+        .and_then(|component| component["short_name"].as_str())
+        .map(String::from);
+    let country = address_component(components, "country")
+        .unwrap_or_else(|| env::var("DEFAULT_COUNTRY").unwrap_or_else(|_| "No country available".to_string()));
+
+    // Google's `vicinity` is usually "street, city", so prefer a proper neighborhood component.
+    let neighborhood = ["neighborhood", "sublocality_level_1", "sublocality"]
+        .iter()
+        .find_map(|kind| address_component(components, kind))
+        .or_else(|| details["vicinity"].as_str().filter(|v| !v.is_empty()).map(String::from));
+
+    let photos = select_photos(
+        details["photos"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|photo| {
+                Some(Photo {
+                    reference: photo["photo_reference"].as_str()?.to_string(),
+                    width: photo["width"].as_u64().unwrap_or(0),
+                    height: photo["height"].as_u64().unwrap_or(0),
+                    attribution: photo["html_attributions"][0].as_str().and_then(parse_attribution),
+                })
+            })
+            .collect(),
+    );
+    let coordinates = details["geometry"]["location"]["lat"].as_f64()
+        .zip(details["geometry"]["location"]["lng"].as_f64());
+    let types = details["types"]
+        .as_array()
+        .map(|types| types.iter().filter_map(|t| t.as_str().map(String::from)).collect())
+        .unwrap_or_default();
+    let opening_hours = details["opening_hours"]["weekday_text"]
+        .as_array()
+        .map(|lines| lines.iter().filter_map(|line| line.as_str().map(String::from)).collect())
+        .unwrap_or_default();
+    // The local format by default, `PHONE_FORMAT=international` for the +<country code> one
+    let (preferred, fallback) = match env::var("PHONE_FORMAT").as_deref() {
+        Ok("international") => ("international_phone_number", "formatted_phone_number"),
+        _ => ("formatted_phone_number", "international_phone_number"),
+    };
+    let phone = details[preferred]
+        .as_str()
+        .or_else(|| details[fallback].as_str())
+        .map(str::trim)
+        .filter(|phone| !phone.is_empty())
+        .map(String::from);
 
     Ok(PlaceDetails {
+        place_id,
         name,
         website,
         price_level,
@@ -145,7 +799,569 @@ fn process_place_details(details: &serde_json::Value) -> Result<PlaceDetails, Bo
         country,
         google_maps_link,
         address,
-        photo_reference,
+        coordinates,
+        types,
+        neighborhood,
+        photos,
+        photo_count: details["photos"].as_array().map_or(0, Vec::len),
+        country_code,
+        rating: details["rating"].as_f64(),
+        rating_count: details["user_ratings_total"].as_u64(),
+        phone,
+        opening_hours,
+        utc_offset: details["utc_offset"].as_i64(),
+        timezone: None,
     })
 }
 
+/// Picks up to `MAX_PHOTOS` (default 1, the cover alone) of Google's photos, leaving out
+/// those below `PHOTO_MIN_WIDTH`/`PHOTO_MIN_HEIGHT`, see [`order_photos`].
+fn select_photos(photos: Vec<Photo>) -> Vec<Photo> {
+    let max_photos = env::var("MAX_PHOTOS").ok().and_then(|v| v.parse::<usize>().ok()).unwrap_or(DEFAULT_MAX_PHOTOS);
+    let photos = photos
+        .into_iter()
+        .filter(|photo| check_photo_size(photo.width, photo.height).is_ok())
+        .collect();
+    order_photos(photos, env::var("GALLERY_ORDER").ok().as_deref(), max_photos)
+}
+
+/// Keeps the first photo as the cover and orders the rest by `order`: Google's order
+/// (`google`, default), `largest` resolution or `landscape` photos first.
+fn order_photos(mut photos: Vec<Photo>, order: Option<&str>, max_photos: usize) -> Vec<Photo> {
+    if let Some(gallery) = photos.get_mut(1..) {
+        match order {
+            Some("largest") => gallery.sort_by_key(|photo| std::cmp::Reverse(photo.width * photo.height)),
+            Some("landscape") => gallery.sort_by_key(|photo| photo.width < photo.height),
+            _ => {}
+        }
+    }
+    photos.truncate(max_photos);
+    photos
+}
+
+/// Parses `PRICE_LEVEL_MAP`, e.g. `0=Free,1=€,2=€€,3=€€€,4=€€€`, where `default=...`
+/// covers any level left out. Returns `None` when no mapping is configured.
+fn price_level_map() -> Result<Option<HashMap<String, String>>, String> {
+    let raw = match env::var("PRICE_LEVEL_MAP") {
+        Ok(raw) if !raw.trim().is_empty() => raw,
+        _ => return Ok(None),
+    };
+
+    let mut map = HashMap::new();
+    for entry in raw.split(',') {
+        let (key, value) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid PRICE_LEVEL_MAP entry '{}', expected level=option", entry))?;
+        let key = key.trim();
+        if !["0", "1", "2", "3", "4", "default"].contains(&key) {
+            return Err(format!("Invalid PRICE_LEVEL_MAP level '{}', expected 0-4 or default", key));
+        }
+        map.insert(key.to_string(), value.trim().to_string());
+    }
+
+    Ok(Some(map))
+}
+
+/// Checks at startup that `PRICE_LEVEL_MAP`, if set, parses and maps every level,
+/// and that `PRICE_SYMBOL`, if set, is a single character.
+pub fn validate_price_config() -> Result<(), String> {
+    if let Some(map) = price_level_map()? {
+        let missing: Vec<&str> = ["0", "1", "2", "3", "4"]
+            .into_iter()
+            .filter(|level| !map.contains_key(*level))
+            .collect();
+        if !missing.is_empty() && !map.contains_key("default") {
+            return Err(format!("PRICE_LEVEL_MAP has no option for level(s) {} and no default", missing.join(", ")));
+        }
+    }
+    if let Ok(symbol) = env::var("PRICE_SYMBOL") {
+        if symbol.graphemes(true).count() != 1 {
+            return Err(format!("PRICE_SYMBOL must be a single character or emoji, got '{}'", symbol));
+        }
+    }
+    match env::var("PRICE_FORMAT").as_deref() {
+        Err(_) | Ok("repeat") | Ok("labels") => Ok(()),
+        Ok(other) => Err(format!("Invalid PRICE_FORMAT '{}', expected repeat or labels", other)),
+    }
+}
+
+/// Formats a price level as fixed labels from `PRICE_LEVEL_MAP` or as `PRICE_SYMBOL`
+/// (default 💵) repeated `level` times. `PRICE_FORMAT` picks one explicitly; otherwise
+/// labels are used whenever a map is configured.
+fn format_price_level(level: i64) -> String {
+    let map = price_level_map().ok().flatten();
+    let use_labels = match env::var("PRICE_FORMAT").as_deref() {
+        Ok("repeat") => false,
+        Ok("labels") => true,
+        _ => map.is_some(),
+    };
+
+    match map.filter(|_| use_labels) {
+        Some(map) => map
+            .get(&level.to_string())
+            .or_else(|| map.get("default"))
+            .cloned()
+            .unwrap_or_else(unknown_price),
+        None => {
+            let symbol = env::var("PRICE_SYMBOL").unwrap_or_else(|_| "💵".to_string());
+            symbol.repeat(level as usize)
+        }
+    }
+}
+
+/// Price written when Google has no price level: `PRICE_UNKNOWN` as the option name
+/// (default ❓), or nothing at all with `PRICE_UNKNOWN=omit`.
+pub(crate) fn unknown_price() -> String {
+    match env::var("PRICE_UNKNOWN") {
+        Ok(value) if value == "omit" => String::new(),
+        Ok(value) if !value.is_empty() => value,
+        _ => "❓".to_string(),
+    }
+}
+
+/// Turns an attribution like `<a href="https://maps.google.com/maps/contrib/1">Jane</a>`
+/// into the contributor name and link.
+fn parse_attribution(html: &str) -> Option<PhotoAttribution> {
+    let link = html
+        .split_once("href=\"")
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .map(|(href, _)| href.to_string());
+
+    let mut name = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => name.push(c),
+            _ => {}
+        }
+    }
+    let name = name.replace("&amp;", "&").trim().to_string();
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(PhotoAttribution { name, link })
+    }
+}
+
+/// Not every place has a locality; falls back to progressively coarser areas.
+fn city_component(components: &[serde_json::Value]) -> Option<String> {
+    ["locality", "postal_town", "administrative_area_level_3", "administrative_area_level_2"]
+        .iter()
+        .find_map(|kind| address_component(components, kind))
+}
+
+fn address_component(components: &[serde_json::Value], kind: &str) -> Option<String> {
+    components
+        .iter()
+        .find(|component| {
+            component["types"].as_array().is_some_and(|types| types.iter().any(|t| t == kind))
+        })
+        .and_then(|component| component["long_name"].as_str())
+        .map(String::from)
+}
+
+/// Maps the specific Google place types (e.g. `bakery`, `italian_restaurant`) to cuisine
+/// names. Generic types like `restaurant` or `food` say nothing about the cuisine and
+/// are ignored, so `None` means Google's types are not confident enough.
+pub fn cuisine_from_types(types: &[String]) -> Option<String> {
+    let with_emoji = env::var("GOOGLE_CUISINE_EMOJI").map(|v| v == "true" || v == "1").unwrap_or(false);
+    let cuisines: Vec<String> = types
+        .iter()
+        .filter_map(|kind| {
+            let cuisine = match kind.as_str() {
+                "bakery" => "Bakery".to_string(),
+                "bar" => "Bar".to_string(),
+                "cafe" => "Cafe".to_string(),
+                "night_club" => "Night Club".to_string(),
+                "restaurant" | "fast_food_restaurant" => return None,
+                other => other.strip_suffix("_restaurant").map(|cuisine| {
+                    cuisine
+                        .split('_')
+                        .map(|word| {
+                            let mut chars = word.chars();
+                            chars.next().map(|c| c.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })?,
+            };
+            match type_emoji(kind).filter(|_| with_emoji) {
+                Some(emoji) => Some(format!("{} {}", emoji, cuisine)),
+                None => Some(cuisine),
+            }
+        })
+        .collect();
+
+    if cuisines.is_empty() {
+        None
+    } else {
+        Some(cuisines.join(", "))
+    }
+}
+
+/// Emoji shown before a cuisine derived from a Google type when `GOOGLE_CUISINE_EMOJI` is set.
+fn type_emoji(kind: &str) -> Option<&'static str> {
+    let emoji = match kind {
+        "bakery" => "🥖",
+        "bar" | "night_club" => "🍸",
+        "cafe" => "☕",
+        "american_restaurant" | "hamburger_restaurant" => "🍔",
+        "chinese_restaurant" => "🥡",
+        "french_restaurant" => "🥐",
+        "indian_restaurant" => "🍛",
+        "italian_restaurant" => "🍝",
+        "japanese_restaurant" | "sushi_restaurant" => "🍣",
+        "korean_restaurant" => "🥘",
+        "mexican_restaurant" => "🌮",
+        "pizza_restaurant" => "🍕",
+        "ramen_restaurant" => "🍜",
+        "seafood_restaurant" => "🦞",
+        "thai_restaurant" | "vietnamese_restaurant" => "🍲",
+        "vegan_restaurant" | "vegetarian_restaurant" => "🥗",
+        _ => return None,
+    };
+    Some(emoji)
+}
+
+/// Drops a leading emoji added by [`cuisine_from_types`], so cuisines from different
+/// sources can be compared by name.
+pub fn strip_cuisine_emoji(cuisine: &str) -> &str {
+    cuisine.trim_start_matches(|c: char| !c.is_alphanumeric()).trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn nearest_place_id_ignores_prominence_order() {
+        let results = json!([
+            { "place_id": "prominent", "geometry": { "location": { "lat": 48.8570, "lng": 2.3530 } } },
+            { "place_id": "nearest", "geometry": { "location": { "lat": 48.8566, "lng": 2.3522 } } },
+            { "place_id": "no_location" },
+        ]);
+        assert_eq!(nearest_place_id(&results, (48.8566, 2.3523)), Some("nearest"));
+        assert_eq!(nearest_place_id(&json!([]), (48.8566, 2.3523)), None);
+    }
+
+    #[test]
+    fn extract_coordinates_from_full_url_paths() {
+        assert_eq!(
+            extract_coordinates("/maps/place/Le+Comptoir/@48.8566,2.3522,17z/data=!3m1!4b1"),
+            Some((48.8566, 2.3522))
+        );
+        assert_eq!(extract_coordinates("/maps/@-33.8688197,151.2092955,15z"), Some((-33.8688197, 151.2092955)));
+        assert_eq!(extract_coordinates("/maps/place/Taqueria/@19.4326,-99.1332,3a,75y,90t"), Some((19.4326, -99.1332)));
+        assert_eq!(extract_coordinates("/maps/place/Somewhere/data=!4m2"), None);
+    }
+
+    #[test]
+    fn parse_lat_lng_checks_ranges() {
+        assert_eq!(parse_lat_lng("-34.6037,-58.3816"), Some((-34.6037, -58.3816)));
+        assert_eq!(parse_lat_lng(" 40.7128 , -74.0060 ,12z"), Some((40.7128, -74.006)));
+        assert_eq!(parse_lat_lng("91.0,10.0"), None);
+        assert_eq!(parse_lat_lng("10.0,-181.0"), None);
+        assert_eq!(parse_lat_lng("Le Comptoir"), None);
+        assert_eq!(parse_lat_lng("48.85"), None);
+    }
+
+    #[test]
+    fn dms_pin_links_are_dropped_pins() {
+        let info = extract_place_info(
+            "https://www.google.com/maps/place/48%C2%B051'24.0%22N+2%C2%B021'08.0%22E/@48.8566667,2.3522222,17z",
+        )
+        .unwrap();
+        assert!(info.dropped_pin);
+        assert_eq!(info.coordinates, Some((48.8566667, 2.3522222)));
+
+        let info = extract_place_info("https://www.google.com/maps/place/33%C2%B052'07.8%22S+151%C2%B012'33.5%22E/@-33.8688,151.2093,17z").unwrap();
+        assert!(info.dropped_pin);
+        assert_eq!(info.coordinates, Some((-33.8688, 151.2093)));
+    }
+
+    #[test]
+    fn coordinates_in_q_are_a_dropped_pin() {
+        let info = extract_place_info("https://maps.google.com/maps?q=-22.9068,-43.1729").unwrap();
+        assert!(info.dropped_pin);
+        assert_eq!(info.coordinates, Some((-22.9068, -43.1729)));
+    }
+
+    #[test]
+    fn named_places_keep_their_coordinates_as_a_bias() {
+        let info = extract_place_info("https://www.google.com/maps/place/Le+Comptoir+du+Relais/@48.8520,2.3388,17z/data=!4m6").unwrap();
+        assert!(!info.dropped_pin);
+        assert_eq!(info.query, "Le Comptoir du Relais");
+        assert_eq!(info.coordinates, Some((48.852, 2.3388)));
+    }
+
+    fn component(name: &str, types: &[&str]) -> serde_json::Value {
+        json!({ "long_name": name, "short_name": name, "types": types })
+    }
+
+    #[test]
+    fn city_prefers_the_locality() {
+        let components = [
+            component("Paris", &["locality", "political"]),
+            component("Département de Paris", &["administrative_area_level_2", "political"]),
+        ];
+        assert_eq!(city_component(&components).as_deref(), Some("Paris"));
+    }
+
+    #[test]
+    fn city_falls_back_to_coarser_areas_in_order() {
+        let postal_town = [
+            component("Greater London", &["administrative_area_level_2", "political"]),
+            component("London", &["postal_town"]),
+        ];
+        assert_eq!(city_component(&postal_town).as_deref(), Some("London"));
+
+        let area_3 = [
+            component("Kreis Lörrach", &["administrative_area_level_2", "political"]),
+            component("Weil am Rhein", &["administrative_area_level_3", "political"]),
+        ];
+        assert_eq!(city_component(&area_3).as_deref(), Some("Weil am Rhein"));
+
+        let area_2 = [component("County Kerry", &["administrative_area_level_2", "political"])];
+        assert_eq!(city_component(&area_2).as_deref(), Some("County Kerry"));
+    }
+
+    #[test]
+    fn sparse_components_have_no_city() {
+        assert_eq!(city_component(&[]), None);
+        assert_eq!(city_component(&[component("Iceland", &["country", "political"])]), None);
+        assert_eq!(city_component(&[json!({ "long_name": "No types" })]), None);
+    }
+
+    #[test]
+    fn country_and_country_code_come_from_the_country_component() {
+        let details = json!({
+            "name": "Café de Flore",
+            "address_components": [
+                component("Paris", &["locality", "political"]),
+                { "long_name": "France", "short_name": "FR", "types": ["country", "political"] },
+            ],
+        });
+        let place = process_place_details(&details).unwrap();
+        assert_eq!(place.city, "Paris");
+        assert_eq!(place.country, "France");
+        assert_eq!(place.country_code.as_deref(), Some("FR"));
+    }
+
+    #[test]
+    fn apple_links_give_name_and_coordinates() {
+        let info = extract_apple_place_info("https://maps.apple.com/?q=Chez%20Janou&ll=48.8571,2.3654&z=16").unwrap();
+        assert_eq!(info.query, "Chez Janou");
+        assert_eq!(info.coordinates, Some((48.8571, 2.3654)));
+        assert!(!info.dropped_pin);
+    }
+
+    #[test]
+    fn apple_links_add_the_address_to_the_query() {
+        let info = extract_apple_place_info(
+            "https://maps.apple.com/?address=2%20Rue%20Roger%20Verlomme,%2075003%20Paris,%20France&name=Chez%20Janou&coordinate=48.8571,2.3654",
+        )
+        .unwrap();
+        assert_eq!(info.query, "Chez Janou, 2 Rue Roger Verlomme, 75003 Paris, France");
+        assert_eq!(info.coordinates, Some((48.8571, 2.3654)));
+    }
+
+    #[test]
+    fn apple_links_fall_back_to_the_search_location_or_no_coordinates() {
+        let info = extract_apple_place_info("https://maps.apple.com/?q=Joe%27s%20Pizza&sll=40.7306,-73.9897").unwrap();
+        assert_eq!(info.coordinates, Some((40.7306, -73.9897)));
+
+        let info = extract_apple_place_info("https://maps.apple.com/?q=Joe%27s%20Pizza").unwrap();
+        assert_eq!(info.query, "Joe's Pizza");
+        assert_eq!(info.coordinates, None);
+    }
+
+    #[test]
+    fn apple_links_need_a_name() {
+        assert!(extract_apple_place_info("https://maps.apple.com/?ll=48.8571,2.3654").is_err());
+    }
+
+    #[test]
+    fn q_place_id_is_a_direct_lookup() {
+        let info = extract_place_info("https://www.google.com/maps/search/?api=1&q=place_id:ChIJLU7jZClu5kcR4PcOOO6p3I0").unwrap();
+        assert_eq!(info.place_id.as_deref(), Some("ChIJLU7jZClu5kcR4PcOOO6p3I0"));
+
+        let info = extract_place_info("https://www.google.com/maps/place/?q=place_id:%20ChIJLU7jZClu5kcR4PcOOO6p3I0").unwrap();
+        assert_eq!(info.place_id.as_deref(), Some("ChIJLU7jZClu5kcR4PcOOO6p3I0"));
+    }
+
+    #[test]
+    fn query_place_id_is_a_direct_lookup() {
+        let info = extract_place_info(
+            "https://www.google.com/maps/search/?api=1&query=Le+Comptoir&query_place_id=ChIJLU7jZClu5kcR4PcOOO6p3I0",
+        )
+        .unwrap();
+        assert_eq!(info.place_id.as_deref(), Some("ChIJLU7jZClu5kcR4PcOOO6p3I0"));
+    }
+
+    #[test]
+    fn empty_place_id_is_ignored() {
+        let info = extract_place_info("https://www.google.com/maps/search/?api=1&q=place_id:").unwrap();
+        assert_eq!(info.place_id, None);
+    }
+
+    #[test]
+    fn global_plus_codes_are_recognized() {
+        assert!(is_plus_code("849VCWC8+R9"));
+        assert!(is_plus_code("8FW4V75V+8Q"));
+        assert!(is_plus_code("849vcwc8+r9"));
+    }
+
+    #[test]
+    fn local_plus_codes_with_a_locality_are_recognized() {
+        assert!(is_plus_code("CWC8+R9 Mountain View, CA"));
+        assert!(is_plus_code("V75V+8Q Paris"));
+        assert!(is_plus_code("GV8Q+X2H Lagos"));
+    }
+
+    #[test]
+    fn other_text_is_not_a_plus_code() {
+        assert!(!is_plus_code("CWC8+"));
+        assert!(!is_plus_code("CWC8+R"));
+        assert!(!is_plus_code("CWC8+R9XY"));
+        assert!(!is_plus_code("CWC+R9"));
+        assert!(!is_plus_code("Bob+Joe's Diner"));
+        assert!(!is_plus_code("AB12+CD"));
+        assert!(!is_plus_code("Le Comptoir"));
+    }
+
+    #[test]
+    fn plus_code_links_are_detected() {
+        let info = extract_place_info("https://www.google.com/maps/place/CWC8%2BR9+Mountain+View,+CA/@37.4219,-122.0841,17z").unwrap();
+        assert_eq!(info.plus_code.as_deref(), Some("CWC8+R9 Mountain View, CA"));
+
+        let info = extract_place_info("https://www.google.com/maps/search/?api=1&query=849VCWC8%2BR9").unwrap();
+        assert_eq!(info.plus_code.as_deref(), Some("849VCWC8+R9"));
+    }
+
+    #[test]
+    fn cid_from_feature_id_reads_the_hex_cid() {
+        assert_eq!(cid_from_feature_id("0x47e66e2964e34e2d:0x8ddca9ee380ef7e0").as_deref(), Some("10222232094831998944"));
+        assert_eq!(cid_from_feature_id("0x47e66e2964e34e2d:0x8ddca9ee380ef7e0,1,,,").as_deref(), Some("10222232094831998944"));
+        assert_eq!(cid_from_feature_id("0x47e66e2964e34e2d"), None);
+        assert_eq!(cid_from_feature_id("0x1:zz"), None);
+    }
+
+    /// Shapes seen after expanding shared links, with the identity each should yield.
+    #[test]
+    fn expanded_url_catalog() {
+        // Classic share link with a customer id
+        let info = extract_place_info("https://maps.google.com/?cid=10222232094831998944&entry=gps").unwrap();
+        assert_eq!(info.cid.as_deref(), Some("10222232094831998944"));
+
+        // Search result link carrying the local business id
+        let info = extract_place_info("https://www.google.com/maps?q=Le+Comptoir&ludocid=10222232094831998944&ibp=gwp;0,7").unwrap();
+        assert_eq!(info.cid.as_deref(), Some("10222232094831998944"));
+        assert_eq!(info.query, "Le Comptoir");
+
+        // Knowledge panel link with only the feature id
+        let info = extract_place_info("https://www.google.com/maps?q=Le+Comptoir&lrd=0x47e66e2964e34e2d:0x8ddca9ee380ef7e0,1,,,").unwrap();
+        assert_eq!(info.cid.as_deref(), Some("10222232094831998944"));
+
+        // ftid query parameter, as in maps.app.goo.gl expansions
+        let info = extract_place_info("https://maps.google.com/maps?q=Le+Comptoir&ftid=0x47e66e2964e34e2d:0x8ddca9ee380ef7e0").unwrap();
+        assert_eq!(info.id.as_deref(), Some("0x47e66e2964e34e2d:0x8ddca9ee380ef7e0"));
+
+        // Desktop place link whose data= segment holds the feature id
+        let info = extract_place_info(
+            "https://www.google.com/maps/place/Le+Comptoir/@48.852,2.3388,17z/data=!3m1!4b1!4m6!3m5!1s0x47e66e2964e34e2d:0x8ddca9ee380ef7e0!8m2!3d48.852!4d2.3388",
+        )
+        .unwrap();
+        assert_eq!(info.id.as_deref(), Some("0x47e66e2964e34e2d:0x8ddca9ee380ef7e0"));
+        assert_eq!(info.place_id, None);
+
+        // A non-numeric cid is ignored rather than looked up
+        let info = extract_place_info("https://maps.google.com/?cid=abc&q=Le+Comptoir").unwrap();
+        assert_eq!(info.cid, None);
+        assert_eq!(info.query, "Le Comptoir");
+    }
+
+    #[test]
+    fn search_paths_give_their_term() {
+        assert_eq!(place_name_from_path("/maps/search/pizza+near+me/@48.85,2.35,14z").as_deref(), Some("pizza near me"));
+        assert_eq!(place_name_from_path("/maps/search/Caf%C3%A9+de+Flore").as_deref(), Some("Café de Flore"));
+        assert_eq!(place_name_from_path("/maps/place/Le+Comptoir/@48.852,2.3388,17z").as_deref(), Some("Le Comptoir"));
+    }
+
+    #[test]
+    fn search_paths_without_a_term_give_nothing() {
+        assert_eq!(place_name_from_path("/maps/search/"), None);
+        assert_eq!(place_name_from_path("/maps/search/@48.85,2.35,14z"), None);
+        assert_eq!(place_name_from_path("/maps/search/48.8566,2.3522"), None);
+        assert_eq!(place_name_from_path("/maps/@48.85,2.35,14z"), None);
+    }
+
+    #[test]
+    fn search_links_go_through_the_text_query() {
+        let info = extract_place_info("https://www.google.com/maps/search/pizza+near+me/@48.85,2.35,14z").unwrap();
+        assert_eq!(info.query, "pizza near me");
+        assert_eq!(info.coordinates, Some((48.85, 2.35)));
+        assert!(!info.dropped_pin);
+
+        let info = extract_place_info("https://www.google.com/maps/search/?api=1&query=Bob+%26+Joe%27s+Diner").unwrap();
+        assert_eq!(info.query, "Bob & Joe's Diner");
+        assert_eq!(info.place_id, None);
+    }
+
+    #[test]
+    fn data_place_id_reads_the_1s_element() {
+        assert_eq!(
+            data_place_id("/maps/place/Le+Comptoir/@48.852,2.3388,17z/data=!3m1!4b1!4m6!3m5!1s0x47e66e2964e34e2d:0x8ddca9ee380ef7e0!8m2").as_deref(),
+            Some("0x47e66e2964e34e2d:0x8ddca9ee380ef7e0")
+        );
+        assert_eq!(data_place_id("/maps/place/X/data=%214m2%213m1%211s0x1%3A0x2").as_deref(), Some("0x1:0x2"));
+        assert_eq!(data_place_id("/maps/place/X/data=!4m5!3m4!1sChIJLU7jZClu5kcR4PcOOO6p3I0!8m2").as_deref(), Some("ChIJLU7jZClu5kcR4PcOOO6p3I0"));
+        assert_eq!(data_place_id("/maps/place/X/data=!4m2!3m1!1s"), None);
+        assert_eq!(data_place_id("/maps/place/X/@48.852,2.3388,17z"), None);
+    }
+
+    #[test]
+    fn full_links_on_each_host_resolve_the_same_place() {
+        for host in ["google.com", "www.google.com", "maps.google.com"] {
+            let info = extract_place_info(&format!(
+                "https://{}/maps/place/Le+Comptoir/@48.852,2.3388,17z/data=!4m5!3m4!1sChIJLU7jZClu5kcR4PcOOO6p3I0!8m2",
+                host
+            ))
+            .unwrap();
+            assert_eq!(info.place_id.as_deref(), Some("ChIJLU7jZClu5kcR4PcOOO6p3I0"), "{}", host);
+        }
+    }
+
+    #[test]
+    fn find_place_url_keeps_the_query_in_one_parameter() {
+        let query = "Bob & Joe's Diner #1 / Café";
+        let url = url::Url::parse(&find_place_url("test-key", query, Some((40.7128, -74.006)))).unwrap();
+        assert_eq!(url.fragment(), None);
+
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(params.get("input").map(String::as_str), Some(query));
+        assert_eq!(params.get("inputtype").map(String::as_str), Some("textquery"));
+        assert_eq!(params.get("key").map(String::as_str), Some("test-key"));
+        assert_eq!(params.get("locationbias").map(String::as_str), Some("point:40.7128,-74.006"));
+        assert!(!params.contains_key(" Joe's Diner "));
+    }
+
+    fn photo(reference: &str, width: u64, height: u64) -> Photo {
+        Photo { reference: reference.to_string(), width, height, attribution: None }
+    }
+
+    #[test]
+    fn order_photos_keeps_the_cover_first_and_caps_the_total() {
+        let photos = vec![photo("cover", 400, 800), photo("small", 100, 200), photo("tall", 300, 900), photo("wide", 800, 400)];
+        let references = |photos: Vec<Photo>| photos.into_iter().map(|p| p.reference).collect::<Vec<_>>();
+
+        assert_eq!(references(order_photos(photos.clone(), None, 1)), ["cover"]);
+        assert_eq!(references(order_photos(photos.clone(), Some("google"), 3)), ["cover", "small", "tall"]);
+        assert_eq!(references(order_photos(photos.clone(), Some("largest"), 3)), ["cover", "wide", "tall"]);
+        assert_eq!(references(order_photos(photos.clone(), Some("landscape"), 2)), ["cover", "wide"]);
+        assert!(order_photos(photos, None, 0).is_empty());
+    }
+}
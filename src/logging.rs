@@ -2,12 +2,70 @@ use log4rs::{
     append::{
         console::ConsoleAppender,
         file::FileAppender,
+        Append,
     },
     config::{Appender, Config, Root},
     encode::pattern::PatternEncoder,
 };
-use log::{info, LevelFilter};
-use std::fs;
+use log::{info, warn, Level, LevelFilter, Record};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+const DEFAULT_DIAGNOSTICS_BUFFER_SIZE: usize = 50;
+
+lazy_static::lazy_static! {
+    static ref RECENT_ERRORS: Mutex<VecDeque<DiagnosticEvent>> = Mutex::new(VecDeque::new());
+}
+
+tokio::task_local! {
+    /// The id of the request being processed by the current task, if any.
+    pub static REQUEST_ID: String;
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticEvent {
+    timestamp: String,
+    request_id: Option<String>,
+    message: String,
+}
+
+/// Keeps the last error-level events in memory for the `/diagnostics` endpoint.
+#[derive(Debug)]
+struct DiagnosticsAppender {
+    capacity: usize,
+}
+
+impl Append for DiagnosticsAppender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        if record.level() > Level::Error || self.capacity == 0 {
+            return Ok(());
+        }
+
+        let event = DiagnosticEvent {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            request_id: REQUEST_ID.try_with(|id| id.clone()).ok(),
+            message: record.args().to_string(),
+        };
+
+        let mut buffer = RECENT_ERRORS.lock().unwrap();
+        while buffer.len() >= self.capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(event);
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+/// Returns up to `limit` of the most recent error events, newest first.
+pub fn recent_errors(limit: usize) -> Vec<DiagnosticEvent> {
+    RECENT_ERRORS.lock().unwrap().iter().rev().take(limit).cloned().collect()
+}
 
 pub fn setup_logging() -> Result<(), Box<dyn std::error::Error>> {
     // Create logs directory if it doesn't exist
@@ -25,14 +83,24 @@ pub fn setup_logging() -> Result<(), Box<dyn std::error::Error>> {
         .encoder(Box::new(PatternEncoder::new(log_pattern)))
         .build("logs/gastropath.log")?;
 
+    // Create the in-memory appender backing /diagnostics
+    let diagnostics = DiagnosticsAppender {
+        capacity: env::var("DIAGNOSTICS_BUFFER_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_DIAGNOSTICS_BUFFER_SIZE),
+    };
+
     // Build the log4rs configuration
     let config = Config::builder()
         .appender(Appender::builder().build("stdout", Box::new(stdout)))
         .appender(Appender::builder().build("file", Box::new(file)))
+        .appender(Appender::builder().build("diagnostics", Box::new(diagnostics)))
         .build(
             Root::builder()
                 .appender("stdout")
                 .appender("file")
+                .appender("diagnostics")
                 .build(LevelFilter::Info),
         )?;
 
@@ -48,3 +116,33 @@ pub fn log_start_message() {
     info!("==================================================");
 }
 
+
+
+/// Appends a raw upstream API response to `logs/raw/<request_id>.jsonl` when
+/// `DEBUG_RAW_RESPONSES` is enabled. Values of any `*KEY*`/`*SECRET*` env var
+/// are redacted before writing.
+pub fn dump_raw_response(request_id: &str, source: &str, response: &serde_json::Value) {
+    if !env::var("DEBUG_RAW_RESPONSES").map(|v| v == "true" || v == "1").unwrap_or(false) {
+        return;
+    }
+
+    let mut line = serde_json::json!({ "source": source, "response": response }).to_string();
+    for (key, value) in env::vars() {
+        let key = key.to_uppercase();
+        if (key.contains("KEY") || key.contains("SECRET")) && !value.is_empty() {
+            line = line.replace(&value, "[REDACTED]");
+        }
+    }
+
+    let result = fs::create_dir_all("logs/raw").and_then(|_| {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(format!("logs/raw/{}.jsonl", request_id))?;
+        writeln!(file, "{}", line)
+    });
+
+    if let Err(e) = result {
+        warn!("Failed to write raw {} response for request {}: {}", source, request_id, e);
+    }
+}
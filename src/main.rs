@@ -1,23 +1,65 @@
-use actix_web::{web, App, HttpServer, Responder, HttpResponse, middleware::Logger};
+use actix_web::{web, App, HttpRequest, HttpServer, Responder, HttpResponse, body::MessageBody, dev::{ServiceRequest, ServiceResponse}, middleware::{from_fn, Condition, Logger, Next}};
 use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use std::env;
 use log::{info, error, warn};
 use actix_governor::{Governor, GovernorConfigBuilder};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use error::{error_status, GastropathError};
 
+mod audit;
+mod config_check;
 mod google_places;
 mod yelp;
+mod foursquare;
+mod cuisine;
+mod error;
 mod notion;
+mod place_provider;
+mod post_process;
 mod cloudinary;
+mod dedupe;
 mod utils;
 mod logging;
+mod metrics;
+mod maintenance;
+mod rate_limit;
+mod takeout;
+mod timing;
+mod webhook;
+
+const RATE_LIMIT_REPLENISH_SECS: u64 = 5;
+const RATE_LIMIT_BURST: u32 = 10;
+const DEFAULT_BATCH_CONCURRENCY: usize = 4;
+const DEFAULT_BATCH_MAX_URLS: usize = 50;
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
 struct AddRestaurantRequest {
-    #[serde(alias = "URL")]
+    #[serde(alias = "URL", default)]
     url: String,
+    /// A bare Google customer id, looked up instead of `url`.
+    cid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AddRestaurantsRequest {
+    urls: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchResult {
+    url: String,
+    status: &'static str,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiagnosticsQuery {
+    limit: Option<usize>,
 }
 
 #[derive(Debug, Serialize)]
@@ -28,6 +70,8 @@ struct ErrorResponse {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RestaurantDetails {
+    place_id: Option<String>,
+    slug: String,
     name: String,
     website: String,
     price_level: String,
@@ -37,82 +81,422 @@ pub struct RestaurantDetails {
     address: String,
     cuisine_type: String,
     photo_reference: Option<String>,
+    neighborhood: Option<String>,
+    note: Option<String>,
+    timezone: Option<String>,
+    photo_count: Option<usize>,
+    rating: Option<f64>,
+    rating_count: Option<u64>,
+    phone: Option<String>,
+    #[serde(default)]
+    opening_hours: Vec<String>,
+    yelp_rating: Option<f64>,
+    country_code: Option<String>,
 }
 
 async fn health_check() -> impl Responder {
     HttpResponse::Ok().body("Server is running")
 }
 
+/// Checks the `X-API-Key` header (or `Authorization: Bearer`) against `API_KEY`.
+/// Always fails when no `API_KEY` is configured.
+fn is_authorized(req: &HttpRequest) -> bool {
+    let expected = match env::var("API_KEY") {
+        Ok(key) if !key.is_empty() => key,
+        _ => return false,
+    };
+
+    let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok());
+    let provided = header("X-API-Key")
+        .or_else(|| header("Authorization").and_then(|v| v.strip_prefix("Bearer ")));
+
+    provided == Some(expected.as_str())
+}
+
+async fn diagnostics(req: HttpRequest, query: web::Query<DiagnosticsQuery>) -> impl Responder {
+    if !is_authorized(&req) {
+        warn!("Unauthorized request to /diagnostics");
+        return HttpResponse::Unauthorized().body("Unauthorized");
+    }
+
+    let errors = logging::recent_errors(query.limit.unwrap_or(usize::MAX));
+    HttpResponse::Ok().json(serde_json::json!({
+        "errors": errors,
+        "rate_limits": rate_limit::snapshot(),
+    }))
+}
+
+/// Runs only the URL validation of `/add_restaurant`, with no network calls, so clients
+/// can give instant feedback on a pasted link.
+async fn validate_url(body: web::Json<AddRestaurantRequest>) -> impl Responder {
+    match utils::validate_and_sanitize_url(&body.url) {
+        Ok(url) => HttpResponse::Ok().json(serde_json::json!({ "valid": true, "url": url })),
+        Err(e) => HttpResponse::build(error_status("VALIDATION_ERROR_STATUS")).json(serde_json::json!({ "valid": false, "error": e })),
+    }
+}
+
+/// Resolves a URL like `/add_restaurant` would and reports what would change in the
+/// matching Notion entry, without writing anything.
+async fn preview_diff(
+    req: HttpRequest,
+    body: web::Json<AddRestaurantRequest>,
+    client: web::Data<Client>,
+) -> impl Responder {
+    if !is_authorized(&req) {
+        warn!("Unauthorized request to /preview/diff");
+        return HttpResponse::Unauthorized().body("Unauthorized");
+    }
+
+    let request_id = chrono::Utc::now().format("%Y%m%d%H%M%S%f").to_string();
+    let sanitized_url = match utils::validate_and_sanitize_url(&body.url) {
+        Ok(url) => url,
+        Err(e) => return HttpResponse::build(error_status("VALIDATION_ERROR_STATUS")).body(e),
+    };
+
+    let mut timings = timing::StageTimings::start();
+    let place_details = match place_provider::current().details(&client, &sanitized_url, &request_id, &mut timings).await {
+        Ok(details) => details,
+        Err(e) => {
+            error!("Error getting place details for preview: {}", e);
+            return HttpResponse::build(e.status()).body(e.to_string());
+        }
+    };
+    let stage_started = Instant::now();
+    let cuisine = cuisine::resolve(&client, &place_details, &request_id).await;
+    timings.record("cuisine", stage_started);
+    let details = restaurant_details(&place_details, cuisine, utils::extract_note(&body.url));
+
+    let stage_started = Instant::now();
+    let diff = notion::diff_entry(&client, &details).await;
+    timings.record("notion", stage_started);
+    match diff {
+        Ok(mut diff) => {
+            if wants_timing(&req) {
+                diff["timing"] = timings.to_json();
+            }
+            HttpResponse::Ok().json(diff)
+        }
+        Err(e) => {
+            error!("Error comparing with Notion entry: {}", e);
+            let e = GastropathError::Notion(e);
+            HttpResponse::build(e.status()).body(e.to_string())
+        }
+    }
+}
+
+/// Starts re-fetching covers for entries that have none (202), or reports on the
+/// running repair (409).
+async fn repair_covers(req: HttpRequest, client: web::Data<Client>) -> impl Responder {
+    if !is_authorized(&req) {
+        warn!("Unauthorized request to /maintenance/covers");
+        return HttpResponse::Unauthorized().body("Unauthorized");
+    }
+
+    if maintenance::start_cover_repair(client.get_ref().clone()) {
+        HttpResponse::Accepted().json(maintenance::cover_repair_report())
+    } else {
+        HttpResponse::Conflict().json(maintenance::cover_repair_report())
+    }
+}
+
+async fn cover_repair_status(req: HttpRequest) -> impl Responder {
+    if !is_authorized(&req) {
+        warn!("Unauthorized request to /maintenance/covers");
+        return HttpResponse::Unauthorized().body("Unauthorized");
+    }
+
+    match maintenance::cover_repair_report() {
+        Some(report) => HttpResponse::Ok().json(report),
+        None => HttpResponse::NotFound().body("No cover repair has run yet"),
+    }
+}
+
 async fn add_restaurant(
+    req: HttpRequest,
     body: actix_web::web::Bytes,
     client: web::Data<Client>,
 ) -> impl Responder {
     let request_id = chrono::Utc::now().format("%Y%m%d%H%M%S%f").to_string();
-    info!("Processing restaurant: {}", String::from_utf8_lossy(&body));
+    logging::REQUEST_ID
+        .scope(request_id.clone(), process_restaurant(body, client, request_id, wants_timing(&req)))
+        .await
+}
+
+/// Adds every URL of `{ "urls": [...] }`, `BATCH_CONCURRENCY` at a time (default 4), and
+/// returns one result per URL in input order. Batches over `BATCH_MAX_URLS` (default 50)
+/// are rejected with 413, as the rate limiter only counts the request itself.
+async fn add_restaurants(body: web::Json<AddRestaurantsRequest>, client: web::Data<Client>) -> impl Responder {
+    let request_id = chrono::Utc::now().format("%Y%m%d%H%M%S%f").to_string();
+    let max_urls = env::var("BATCH_MAX_URLS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_BATCH_MAX_URLS);
+    if body.urls.len() > max_urls {
+        warn!("Rejected a batch of {} URLs, the limit is {}", body.urls.len(), max_urls);
+        metrics::record_rejected();
+        return HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "error": format!("A batch can hold at most {} URLs, got {}", max_urls, body.urls.len()),
+        }));
+    }
+    let concurrency = env::var("BATCH_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_BATCH_CONCURRENCY);
+    let urls: Vec<&str> = body.urls.iter().map(|url| url.trim()).collect();
+    info!("Processing a batch of {} URLs, {} at a time", urls.len(), concurrency);
+
+    let results = logging::REQUEST_ID
+        .scope(request_id.clone(), process_urls(&client, &urls, &request_id, concurrency))
+        .await;
+    HttpResponse::Ok().json(results)
+}
+
+/// Whether the request asked for stage timings with `?timing=true`.
+fn wants_timing(req: &HttpRequest) -> bool {
+    url::form_urlencoded::parse(req.query_string().as_bytes()).any(|(key, value)| key == "timing" && (value == "true" || value == "1"))
+}
+
+async fn process_restaurant(
+    body: actix_web::web::Bytes,
+    client: web::Data<Client>,
+    request_id: String,
+    timing: bool,
+) -> HttpResponse {
+    let body = match std::str::from_utf8(&body) {
+        Ok(body) => body,
+        Err(_) => {
+            error!("Rejected request body of {} bytes: not valid UTF-8", body.len());
+            return invalid_format("Request body is not valid UTF-8 text");
+        }
+    };
+    info!("Processing restaurant: {}", body);
 
     // Try to parse the request body
-    let req = match serde_json::from_slice::<AddRestaurantRequest>(&body) {
+    let req = match serde_json::from_str::<AddRestaurantRequest>(body) {
         Ok(req) => req,
+        Err(e) if e.is_syntax() || e.is_eof() => {
+            error!("Request body is not JSON: {}", e);
+            return invalid_format("Request body is not valid JSON");
+        }
         Err(e) => {
             let error_msg = format!("Invalid request format: {}", e);
             error!("{}", error_msg);
-            return HttpResponse::BadRequest().body(error_msg);
+            return invalid_format(&error_msg);
         }
     };
 
-    let sanitized_url = match utils::validate_and_sanitize_url(&req.url) {
-        Ok(url) => url,
+    // Simple clients (e.g. a Shortcut text box) may paste several URLs into one field
+    let urls: Vec<&str> = req.url.split_whitespace().collect();
+    if urls.len() > 1 {
+        info!("Processing {} URLs from a single request", urls.len());
+        let results = process_urls(&client, &urls, &request_id, 1).await;
+        return HttpResponse::Ok().json(results);
+    }
+
+    if req.cid.is_none() && req.url.trim().is_empty() {
+        error!("Request body has neither a url nor a cid");
+        return invalid_format("Request body needs a url or a cid");
+    }
+
+    let identifier = match req.cid.as_deref() {
+        Some(cid) => utils::validate_cid(cid).map(|cid| format!("cid:{}", cid)),
+        None => utils::validate_and_sanitize_url(&req.url),
+    };
+    let sanitized_url = match identifier {
+        Ok(identifier) => identifier,
         Err(e) => {
-            error!("URL validation failed: {}", e);
-            return HttpResponse::BadRequest().body(e);
+            error!("Validation failed: {}", e);
+            metrics::record_rejected();
+            return HttpResponse::build(error_status("VALIDATION_ERROR_STATUS")).body(e);
         }
     };
 
-    info!("Getting place details for: {}", sanitized_url);
+    let note = utils::extract_note(&req.url);
+    let (result, timings) = add_place_timed(&client, &sanitized_url, note, &request_id, None).await;
+    let status = match &result {
+        Ok(_) => actix_web::http::StatusCode::OK,
+        Err(e) => e.status(),
+    };
+    // The plain-text body stays the default; timings need a JSON envelope
+    match (result, timing) {
+        (Ok(message), true) => HttpResponse::build(status).json(serde_json::json!({ "message": message, "timing": timings.to_json() })),
+        (Err(e), true) => HttpResponse::build(status).json(serde_json::json!({ "error": e.to_string(), "timing": timings.to_json() })),
+        (Ok(message), false) => HttpResponse::build(status).body(message),
+        (Err(e), false) => HttpResponse::build(status).body(e.to_string()),
+    }
+}
 
-    let place_details = match google_places::get_place_details(&client, &sanitized_url).await {
-        Ok(details) => details,
-        Err(e) => {
-            error!("Error getting place details: {}", e);
-            return HttpResponse::InternalServerError().body(format!("Failed to get place details: {}", e));
+/// Validation error response describing what the request body should look like.
+fn invalid_format(error: &str) -> HttpResponse {
+    metrics::record_rejected();
+    HttpResponse::build(error_status("VALIDATION_ERROR_STATUS")).json(ErrorResponse {
+        error: error.to_string(),
+        expected_format: serde_json::json!({ "url": "https://maps.app.goo.gl/example" }),
+    })
+}
+
+/// Processes each URL independently, `concurrency` at a time, so a failure only
+/// affects its own entry in the results. Every URL is validated up front, so invalid
+/// ones are reported without spending any API quota. Results keep the input order.
+/// Existing entries are looked up in the preloaded index when `NOTION_PRELOAD_EXISTING` is on.
+async fn process_urls(client: &Client, urls: &[&str], request_id: &str, concurrency: usize) -> Vec<BatchResult> {
+    let validated: Vec<Result<String, String>> = urls.iter().map(|url| utils::validate_and_sanitize_url(url)).collect();
+    let invalid = validated.iter().filter(|v| v.is_err()).count();
+    if invalid > 0 {
+        warn!("{} of {} URLs failed validation and will be skipped", invalid, urls.len());
+    }
+    for (url, validation) in urls.iter().zip(&validated) {
+        if let Err(e) = validation {
+            error!("URL validation failed for {}: {}", url, e);
+            metrics::record_rejected();
         }
+    }
+
+    let entry_index = if invalid < urls.len() {
+        notion::EntryIndex::load_if_enabled(client).await.map(Arc::new)
+    } else {
+        None
     };
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut handles = Vec::with_capacity(urls.len());
+    for (index, (url, validation)) in urls.iter().zip(validated).enumerate() {
+        let sanitized_url = match validation {
+            Ok(sanitized_url) => sanitized_url,
+            Err(e) => {
+                handles.push(Err(e));
+                continue;
+            }
+        };
 
-    let cover_url = match cloudinary::upload_image(&client, &place_details.photo_reference).await {
-        Ok(url) => {
-            info!("Updating {} - Cover Image: Updated", place_details.name);
-            Some(url)
-        },
-        Err(e) => {
-            warn!("Failed to upload image for {}: {}", place_details.name, e);
-            None
+        let permit = semaphore.clone().acquire_owned().await.expect("semaphore is never closed");
+        if let Some(entry_index) = &entry_index {
+            entry_index.refresh_if_stale(client).await;
         }
-    };
+        let client = client.clone();
+        let entry_index = entry_index.clone();
+        let note = utils::extract_note(url);
+        let item_request_id = format!("{}-{}", request_id, index + 1);
+        handles.push(Ok(actix_web::rt::spawn(logging::REQUEST_ID.scope(item_request_id.clone(), async move {
+            let outcome = add_place(&client, &sanitized_url, note, &item_request_id, entry_index.as_deref()).await;
+            drop(permit);
+            outcome
+        }))));
+    }
 
-    let cuisine_type = match yelp::get_cuisine_type(&client, &place_details.name, &place_details.city).await {
-        Ok(cuisine) => {
-            info!("Updating {} - Cuisine Type: {}", place_details.name, cuisine);
-            cuisine
-        },
+    let mut results = Vec::with_capacity(urls.len());
+    for (url, handle) in urls.iter().zip(handles) {
+        let outcome = match handle {
+            Ok(handle) => handle.await.unwrap_or_else(|e| Err(format!("Processing failed: {}", e))),
+            Err(e) => Err(e),
+        };
+        results.push(match outcome {
+            Ok(message) => BatchResult { url: url.to_string(), status: "success", message },
+            Err(message) => BatchResult { url: url.to_string(), status: "error", message },
+        });
+    }
+    results
+}
+
+/// Runs the resolution pipeline for a validated URL or text query and writes the
+/// result to Notion, along with the user's note if any. Returns the Notion outcome
+/// message or a user-facing error.
+async fn add_place(
+    client: &Client,
+    identifier: &str,
+    note: Option<String>,
+    request_id: &str,
+    entry_index: Option<&notion::EntryIndex>,
+) -> Result<String, String> {
+    add_place_timed(client, identifier, note, request_id, entry_index).await.0.map_err(|e| e.to_string())
+}
+
+/// [`add_place`], also returning the stage timings.
+async fn add_place_timed(
+    client: &Client,
+    identifier: &str,
+    note: Option<String>,
+    request_id: &str,
+    entry_index: Option<&notion::EntryIndex>,
+) -> (Result<String, GastropathError>, timing::StageTimings) {
+    let mut timings = timing::StageTimings::start();
+    let mut resolved = None;
+    let result = run_pipeline(client, identifier, note, request_id, entry_index, &mut timings, &mut resolved).await;
+    timings.log(request_id);
+    metrics::record_request(&result, timings.total_ms());
+    let outcome = result.as_ref().map(String::clone).map_err(|e| e.to_string());
+    audit::record(request_id, identifier, resolved.as_ref(), &outcome);
+    (result, timings)
+}
+
+async fn run_pipeline(
+    client: &Client,
+    identifier: &str,
+    note: Option<String>,
+    request_id: &str,
+    entry_index: Option<&notion::EntryIndex>,
+    timings: &mut timing::StageTimings,
+    resolved: &mut Option<RestaurantDetails>,
+) -> Result<String, GastropathError> {
+    info!("Getting place details for: {}", identifier);
+
+    let place_details = match place_provider::current().details(client, identifier, request_id, timings).await {
+        Ok(details) => details,
         Err(e) => {
-            warn!("Failed to get cuisine type for {}: {}", place_details.name, e);
-            "❓".to_string()
+            error!("{}", e);
+            return Err(e);
         }
     };
 
-    let restaurant_details = RestaurantDetails {
-        name: place_details.name.clone(),
-        website: place_details.website.clone(),
-        price_level: place_details.price_level.clone(),
-        city: place_details.city.clone(),
-        country: place_details.country.clone(),
-        google_maps_link: place_details.google_maps_link.clone(),
-        address: place_details.address.clone(),
-        cuisine_type: cuisine_type.clone(),
-        photo_reference: place_details.photo_reference.clone(),
+    if let Some(issue) = utils::name_quality_issue(&place_details.name, &place_details.address) {
+        let message = format!(
+            "Suspicious place name '{}': {}. Please share a more specific link to the restaurant",
+            place_details.name, issue
+        );
+        if env::var("NAME_QUALITY_CHECK").as_deref() == Ok("warn") {
+            warn!("{}", message);
+        } else if env::var("NAME_QUALITY_CHECK").as_deref() != Ok("off") {
+            error!("{}", message);
+            return Err(GastropathError::SuspiciousName(message));
+        }
+    }
+
+    let in_flight = match &place_details.place_id {
+        Some(place_id) => match dedupe::claim(place_id).await {
+            dedupe::Claim::Duplicate(message) => return Ok(message),
+            dedupe::Claim::Claimed(in_flight) => Some(in_flight),
+        },
+        None => None,
     };
 
+    let stage_started = Instant::now();
+    // The first photo that uploads becomes the cover, the rest go into the page body
+    let mut cover = None;
+    let mut gallery = Vec::new();
+    let uploads = upload_photos(client, &place_details, &place_details.photos).await;
+    for (index, (photo, upload)) in place_details.photos.iter().zip(uploads).enumerate() {
+        match upload {
+            Ok(image) if cover.is_none() => {
+                info!("Updating {} - Cover Image: Updated", place_details.name);
+                cover = Some((image, photo.attribution.clone()));
+            }
+            Ok(image) => gallery.push(notion::image_block(&image, photo.attribution.as_ref())),
+            Err(e) => warn!("Failed to upload photo {} for {}: {}", index + 1, place_details.name, e),
+        }
+    }
+    if place_details.photos.is_empty() {
+        warn!("Failed to upload image for {}: No photo reference provided", place_details.name);
+    }
+    timings.record("image_upload", stage_started);
+
+    let stage_started = Instant::now();
+    let cuisine = cuisine::resolve(client, &place_details, request_id).await;
+    timings.record("cuisine", stage_started);
+
+    let restaurant_details = restaurant_details(&place_details, cuisine, note);
+
     // Log all the details
+    info!("Updating {} - slug: {}", place_details.name, restaurant_details.slug);
     info!("Updating {} - name: {}", place_details.name, restaurant_details.name);
     info!("Updating {} - website: {}", place_details.name, restaurant_details.website);
     info!("Updating {} - price_level: {}", place_details.name, restaurant_details.price_level);
@@ -120,16 +504,42 @@ async fn add_restaurant(
     info!("Updating {} - country: {}", place_details.name, restaurant_details.country);
     info!("Updating {} - google_maps_link: {}", place_details.name, restaurant_details.google_maps_link);
     info!("Updating {} - address: {}", place_details.name, restaurant_details.address);
+    info!("Updating {} - neighborhood: {:?}", place_details.name, restaurant_details.neighborhood);
+    info!("Updating {} - note: {:?}", place_details.name, restaurant_details.note);
+    info!("Updating {} - timezone: {:?}", place_details.name, restaurant_details.timezone);
+    info!("Updating {} - rating: {:?} ({:?} reviews), Yelp rating: {:?}", place_details.name, restaurant_details.rating, restaurant_details.rating_count, restaurant_details.yelp_rating);
     info!("Updating {} - cuisine_type: {}", place_details.name, restaurant_details.cuisine_type);
 
-    match notion::create_or_update_entry(&client, restaurant_details, cover_url).await {
+    let stage_started = Instant::now();
+    let mut children = Vec::new();
+    // The cover always leads the page's images, the gallery follows it
+    let show_cover = env::var("NOTION_PHOTO_CAPTION").map(|v| v == "true" || v == "1").unwrap_or(false) || !gallery.is_empty();
+    if show_cover {
+        if let Some((cover, attribution)) = &cover {
+            children.push(notion::image_block(cover, attribution.as_ref()));
+        }
+    }
+    children.extend(gallery);
+    let cover = cover.map(|(cover, _)| cover);
+
+    *resolved = Some(restaurant_details.clone());
+    let result = notion::create_or_update_entry(client, restaurant_details, cover, children, entry_index).await;
+    timings.record("notion", stage_started);
+
+    if let (Some(in_flight), Ok(message)) = (&in_flight, &result) {
+        in_flight.succeeded(message);
+    }
+
+    match result {
         Ok(message) => {
             info!("{}", message);
-            HttpResponse::Ok().body(message)
+            webhook::notify(client, format!("✅ {} ({}): {}", place_details.name, place_details.city, message));
+            Ok(message)
         },
         Err(e) => {
             error!("Error adding restaurant to Notion: {}", e);
-            HttpResponse::InternalServerError().body(e)
+            webhook::notify(client, format!("❌ {} ({}): {}", place_details.name, place_details.city, e));
+            Err(GastropathError::Notion(e))
         }
     }
 }
@@ -148,6 +558,113 @@ fn log_environment_variables() {
     info!("Environment variables: {}", serde_json::to_string_pretty(&env_vars).unwrap());
 }
 
+fn restaurant_details(place_details: &google_places::PlaceDetails, cuisine: cuisine::Cuisine, note: Option<String>) -> RestaurantDetails {
+    RestaurantDetails {
+        place_id: place_details.place_id.clone(),
+        slug: utils::restaurant_slug(place_details.place_id.as_deref(), &place_details.name, &place_details.city),
+        name: place_details.name.clone(),
+        website: place_details.website.clone(),
+        price_level: place_details.price_level.clone(),
+        city: place_details.city.clone(),
+        country: place_details.country.clone(),
+        google_maps_link: place_details.google_maps_link.clone(),
+        address: place_details.address.clone(),
+        cuisine_type: cuisine.name,
+        photo_reference: place_details.photos.first().map(|photo| photo.reference.clone()),
+        neighborhood: place_details.neighborhood.clone(),
+        note,
+        timezone: place_details.timezone.clone(),
+        photo_count: Some(place_details.photo_count).filter(|count| *count > 0),
+        rating: place_details.rating,
+        rating_count: place_details.rating_count,
+        phone: place_details.phone.clone(),
+        opening_hours: place_details.opening_hours.clone(),
+        yelp_rating: cuisine.yelp_rating,
+        country_code: place_details.country_code.clone(),
+    }
+}
+
+/// Stores the place's first photo as its cover, see [`upload_photos`].
+pub(crate) async fn upload_cover(client: &Client, place_details: &google_places::PlaceDetails) -> Result<notion::Cover, GastropathError> {
+    let photo = place_details
+        .photos
+        .first()
+        .ok_or_else(|| GastropathError::Other("No photo reference provided".to_string()))?;
+    upload_photos(client, place_details, std::slice::from_ref(photo)).await.remove(0)
+}
+
+/// Stores the photos according to `COVER_STORAGE`: on Cloudinary (default) or uploaded
+/// straight to Notion. Each photo is uploaded on its own, so the results line up with
+/// `photos` and one failing doesn't affect the others.
+async fn upload_photos(
+    client: &Client,
+    place_details: &google_places::PlaceDetails,
+    photos: &[google_places::Photo],
+) -> Vec<Result<notion::Cover, GastropathError>> {
+    if env::var("COVER_STORAGE").as_deref() != Ok("notion") {
+        let references: Vec<&str> = photos.iter().map(|photo| photo.reference.as_str()).collect();
+        return cloudinary::upload_images(client, &references)
+            .await
+            .into_iter()
+            .map(|url| url.map(notion::Cover::External))
+            .collect();
+    }
+
+    let mut results = Vec::new();
+    for (index, photo) in photos.iter().enumerate() {
+        results.push(upload_to_notion(client, place_details, &photo.reference, index).await);
+    }
+    results
+}
+
+async fn upload_to_notion(
+    client: &Client,
+    place_details: &google_places::PlaceDetails,
+    reference: &str,
+    index: usize,
+) -> Result<notion::Cover, GastropathError> {
+    info!("Uploading image to Notion");
+    let (bytes, content_type) = google_places::download_photo(client, reference).await?;
+    let extension = content_type.rsplit('/').next().unwrap_or("jpg");
+    let slug = utils::restaurant_slug(place_details.place_id.as_deref(), &place_details.name, &place_details.city);
+    let filename = match index {
+        0 => format!("{}.{}", slug, extension),
+        index => format!("{}-{}.{}", slug, index, extension),
+    };
+    let upload_id = notion::upload_file(client, bytes, &filename, &content_type).await.map_err(GastropathError::Notion)?;
+    Ok(notion::Cover::Uploaded(upload_id))
+}
+
+/// Routes limited by the write limits; everything else counts as a read.
+const WRITE_ROUTES: [&str; 3] = ["/add_restaurant", "/add_restaurants", "/maintenance/covers"];
+
+/// In `RATE_LIMIT_MODE=queue`, holds the request until the client's slot comes up, before
+/// it reaches the handler.
+async fn queue_request(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if let Some(queues) = req.app_data::<web::Data<rate_limit::RequestQueues>>().cloned() {
+        if !queues.acquire(&req, WRITE_ROUTES.contains(&req.path())).await {
+            return Err(actix_web::error::ErrorTooManyRequests("Too many requests"));
+        }
+    }
+    next.call(req).await
+}
+
+/// Governor settings for one class of routes (`WRITE` or `READ`): a request token is
+/// replenished every `RATE_LIMIT_<KIND>_REPLENISH_SECS`, up to `RATE_LIMIT_<KIND>_BURST`.
+fn route_rate_limit(kind: &str) -> (u64, u32) {
+    let replenish = env::var(format!("RATE_LIMIT_{}_REPLENISH_SECS", kind))
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(RATE_LIMIT_REPLENISH_SECS);
+    let burst = env::var(format!("RATE_LIMIT_{}_BURST", kind))
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(RATE_LIMIT_BURST);
+    (replenish, burst)
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
@@ -160,26 +677,134 @@ async fn main() -> std::io::Result<()> {
 
     logging::log_start_message();
     log_environment_variables();
+    utils::log_provider_timeouts();
 
-    let client = Client::new();
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--check-config") {
+        let passed = match utils::http_client_builder().build() {
+            Ok(client) => config_check::run(&client, args.iter().any(|arg| arg == "--ping")).await,
+            Err(e) => {
+                println!("FAILED  HTTP client: {}", e);
+                false
+            }
+        };
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    if let Err(e) = google_places::validate_price_config()
+        .and_then(|_| notion::validate_property_types())
+        .and_then(|_| notion::validate_property_map())
+        .and_then(|_| notion::validate_rating_tiers())
+        .and_then(|_| place_provider::validate())
+        .and_then(|_| metrics::validate_exporter()) {
+        error!("Invalid configuration: {}", e);
+        return Ok(());
+    }
+    info!("Place details provider: {}", place_provider::current().name());
+    if env::var("COVER_STORAGE").as_deref() != Ok("notion") {
+        if let Err(e) = cloudinary::validate_config() {
+            warn!("{}, restaurants will be added without a cover image", e);
+        }
+    }
+
+    let client = match utils::http_client_builder().build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to build HTTP client: {}", e);
+            return Ok(());
+        }
+    };
+    google_places::set_rating_fields(notion::wants_rating(&client).await);
+
+    if args.get(1).map(String::as_str) == Some("import-takeout") {
+        match args.get(2) {
+            Some(path) => {
+                takeout::import(&client, path).await;
+                metrics::log_summary();
+            }
+            None => eprintln!("Usage: gastropath import-takeout <Saved Places.json>"),
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("repair-covers") {
+        maintenance::run_cover_repair(&client).await;
+        return Ok(());
+    }
 
     info!("Starting Gastropath server");
 
-    HttpServer::new(move || {
-        let governor_config = GovernorConfigBuilder::default()
-            .per_second(5)
-            .burst_size(10)
+    let (write_replenish, write_burst) = route_rate_limit("WRITE");
+    let (read_replenish, read_burst) = route_rate_limit("READ");
+    info!("Rate limits - writes: 1 per {}s (burst {}), reads: 1 per {}s (burst {})", write_replenish, write_burst, read_replenish, read_burst);
+
+    let key_extractor = match rate_limit::ClientKeyExtractor::from_env() {
+        Ok(key_extractor) => key_extractor,
+        Err(e) => {
+            error!("Invalid configuration: {}", e);
+            return Ok(());
+        }
+    };
+    let request_queues = rate_limit::RequestQueues::from_env(
+        (write_replenish, write_burst),
+        (read_replenish, read_burst),
+        key_extractor.clone(),
+    )
+    .map(web::Data::new);
+
+    metrics::spawn_periodic_summary();
+
+    let result = HttpServer::new(move || {
+        let write_governor_config = GovernorConfigBuilder::default()
+            .per_second(write_replenish)
+            .burst_size(write_burst)
+            .key_extractor(key_extractor.clone())
             .finish()
             .unwrap();
-
-        App::new()
+        let read_governor_config = GovernorConfigBuilder::default()
+            .per_second(read_replenish)
+            .burst_size(read_burst)
+            .key_extractor(key_extractor.clone())
+            .finish()
+            .unwrap();
+        let governed = request_queues.is_none();
+        let mut app = App::new()
+            .wrap(from_fn(queue_request))
             .wrap(Logger::default())
-            .wrap(Governor::new(&governor_config))
-            .app_data(web::Data::new(client.clone()))
-            .route("/health", web::get().to(health_check))
-            .route("/add_restaurant", web::post().to(add_restaurant))
+            .app_data(web::Data::new(client.clone()));
+        if let Some(request_queues) = &request_queues {
+            app = app.app_data(request_queues.clone());
+        }
+
+        app
+            .service(
+                web::resource("/add_restaurant")
+                    .wrap(Condition::new(governed, Governor::new(&write_governor_config)))
+                    .route(web::post().to(add_restaurant)),
+            )
+            .service(
+                web::resource("/add_restaurants")
+                    .wrap(Condition::new(governed, Governor::new(&write_governor_config)))
+                    .route(web::post().to(add_restaurants)),
+            )
+            .service(
+                web::resource("/maintenance/covers")
+                    .wrap(Condition::new(governed, Governor::new(&write_governor_config)))
+                    .route(web::post().to(repair_covers))
+                    .route(web::get().to(cover_repair_status)),
+            )
+            .service(
+                web::scope("")
+                    .wrap(Condition::new(governed, Governor::new(&read_governor_config)))
+                    .route("/health", web::get().to(health_check))
+                    .route("/diagnostics", web::get().to(diagnostics))
+                    .route("/preview/diff", web::post().to(preview_diff))
+                    .route("/validate", web::post().to(validate_url)),
+            )
     })
     .bind("0.0.0.0:3754")?
     .run()
-    .await
+    .await;
+
+    metrics::log_summary();
+    result
 }
\ No newline at end of file
@@ -0,0 +1,171 @@
+use reqwest::Client;
+use serde::Serialize;
+use std::env;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+use log::{info, warn, error};
+use crate::notion::{self, CoverlessPage};
+use crate::timing::StageTimings;
+
+const DEFAULT_MAINTENANCE_CONCURRENCY: usize = 3;
+
+lazy_static::lazy_static! {
+    /// Report of the running or last finished cover repair.
+    static ref COVER_REPAIR_REPORT: Mutex<Option<CoverRepairReport>> = Mutex::new(None);
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverRepairReport {
+    pub running: bool,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub scanned: usize,
+    pub repaired: usize,
+    pub skipped_without_place_id: usize,
+    pub failures: Vec<CoverRepairFailure>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverRepairFailure {
+    pub page_id: String,
+    pub name: String,
+    pub error: String,
+}
+
+/// Starts repairing missing covers in the background. Returns `false` when a
+/// repair is already running.
+pub fn start_cover_repair(client: Client) -> bool {
+    if !begin_report() {
+        return false;
+    }
+    actix_web::rt::spawn(async move {
+        repair_covers(&client).await;
+    });
+    true
+}
+
+/// Runs a cover repair to completion, or returns `None` when one is already running.
+pub async fn run_cover_repair(client: &Client) -> Option<CoverRepairReport> {
+    if !begin_report() {
+        return None;
+    }
+    Some(repair_covers(client).await)
+}
+
+pub fn cover_repair_report() -> Option<CoverRepairReport> {
+    COVER_REPAIR_REPORT.lock().unwrap().clone()
+}
+
+/// Finds entries without a cover, looks their photo up again by stored place_id and
+/// sets a new cover, `MAINTENANCE_CONCURRENCY` entries at a time (default 3).
+async fn repair_covers(client: &Client) -> CoverRepairReport {
+    info!("Scanning Notion for entries without a cover");
+
+    let pages = match notion::pages_without_cover(client).await {
+        Ok(pages) => pages,
+        Err(e) => {
+            error!("Cover repair failed to scan Notion: {}", e);
+            return finish_report(|report| report.error = Some(e));
+        }
+    };
+    update_report(|report| report.scanned = pages.len());
+    info!("Found {} entries without a cover", pages.len());
+
+    let concurrency = env::var("MAINTENANCE_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_MAINTENANCE_CONCURRENCY);
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+
+    let mut handles = Vec::new();
+    for page in pages {
+        if page.place_id.is_none() {
+            warn!("Skipping cover repair for {}: no stored place_id", page.name);
+            update_report(|report| report.skipped_without_place_id += 1);
+            continue;
+        }
+
+        let permit = semaphore.clone().acquire_owned().await.expect("semaphore is never closed");
+        let client = client.clone();
+        handles.push(actix_web::rt::spawn(async move {
+            let result = repair_cover(&client, &page).await;
+            drop(permit);
+            (page, result)
+        }));
+    }
+
+    for handle in handles {
+        let (page, result) = match handle.await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                error!("Cover repair task failed: {}", e);
+                continue;
+            }
+        };
+        match result {
+            Ok(()) => {
+                info!("Repaired cover for {}", page.name);
+                update_report(|report| report.repaired += 1);
+            }
+            Err(e) => {
+                warn!("Failed to repair cover for {}: {}", page.name, e);
+                update_report(|report| report.failures.push(CoverRepairFailure { page_id: page.page_id, name: page.name, error: e }));
+            }
+        }
+    }
+
+    let report = finish_report(|_| {});
+    info!(
+        "Cover repair finished: {} of {} repaired, {} without place_id, {} failed",
+        report.repaired, report.scanned, report.skipped_without_place_id, report.failures.len()
+    );
+    report
+}
+
+async fn repair_cover(client: &Client, page: &CoverlessPage) -> Result<(), String> {
+    let place_id = page.place_id.as_deref().ok_or("No stored place_id")?;
+    let request_id = chrono::Utc::now().format("%Y%m%d%H%M%S%f").to_string();
+    let mut timings = StageTimings::start();
+
+    let details = crate::place_provider::current().details(client, &format!("place_id:{}", place_id), &request_id, &mut timings)
+        .await
+        .map_err(|e| e.to_string())?;
+    let cover = crate::upload_cover(client, &details).await.map_err(|e| e.to_string())?;
+    notion::set_cover(client, &page.page_id, &cover).await
+}
+
+/// Resets the report for a new run, unless one is already running.
+fn begin_report() -> bool {
+    let mut report = COVER_REPAIR_REPORT.lock().unwrap();
+    if report.as_ref().is_some_and(|r| r.running) {
+        return false;
+    }
+    *report = Some(CoverRepairReport {
+        running: true,
+        started_at: chrono::Utc::now().to_rfc3339(),
+        finished_at: None,
+        scanned: 0,
+        repaired: 0,
+        skipped_without_place_id: 0,
+        failures: Vec::new(),
+        error: None,
+    });
+    true
+}
+
+fn update_report(update: impl FnOnce(&mut CoverRepairReport)) {
+    if let Some(report) = COVER_REPAIR_REPORT.lock().unwrap().as_mut() {
+        update(report);
+    }
+}
+
+fn finish_report(update: impl FnOnce(&mut CoverRepairReport)) -> CoverRepairReport {
+    let mut report = COVER_REPAIR_REPORT.lock().unwrap();
+    let report = report.as_mut().expect("report is created before a repair starts");
+    update(report);
+    report.running = false;
+    report.finished_at = Some(chrono::Utc::now().to_rfc3339());
+    report.clone()
+}
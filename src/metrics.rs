@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::net::UdpSocket;
+use std::sync::Mutex;
+use std::time::Duration;
+use log::{info, warn};
+use crate::error::GastropathError;
+
+lazy_static::lazy_static! {
+    static ref METRICS: Mutex<Metrics> = Mutex::new(Metrics::default());
+    static ref STATSD: Option<StatsdExporter> = StatsdExporter::from_env().unwrap_or_else(|e| {
+        warn!("Metrics export disabled: {}", e);
+        None
+    });
+}
+
+/// Pushes every counter update to StatsD as it happens, under the same names the
+/// summary uses, e.g. `gastropath.failures.not_found:1|c`.
+struct StatsdExporter {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+impl StatsdExporter {
+    /// Set up when `METRICS_EXPORTER=statsd`, sending to `STATSD_ADDR` (host:port) with
+    /// names prefixed by `STATSD_PREFIX` (default `gastropath`).
+    fn from_env() -> Result<Option<StatsdExporter>, String> {
+        match env::var("METRICS_EXPORTER").unwrap_or_default().to_lowercase().as_str() {
+            "" | "none" => return Ok(None),
+            "statsd" => {}
+            other => return Err(format!("Unknown METRICS_EXPORTER '{}', expected statsd or none", other)),
+        }
+        let address = env::var("STATSD_ADDR").map_err(|_| "STATSD_ADDR must be set for the statsd exporter".to_string())?;
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+        socket.connect(&address).map_err(|e| format!("Invalid STATSD_ADDR '{}': {}", address, e))?;
+        socket.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let prefix = env::var("STATSD_PREFIX").unwrap_or_else(|_| "gastropath".to_string());
+        info!("Exporting metrics to StatsD at {}", address);
+        Ok(Some(StatsdExporter { socket, prefix }))
+    }
+
+    fn send(&self, name: &str, value: u128, kind: &str) {
+        // Metrics are best effort, a lost datagram is not worth a warning per request
+        let _ = self.socket.send(format!("{}.{}:{}|{}", self.prefix, name, value, kind).as_bytes());
+    }
+}
+
+fn export(name: &str, value: u128, kind: &str) {
+    if let Some(exporter) = STATSD.as_ref() {
+        exporter.send(name, value, kind);
+    }
+}
+
+/// Checks at startup that `METRICS_EXPORTER` and its settings are usable.
+pub fn validate_exporter() -> Result<(), String> {
+    StatsdExporter::from_env().map(|_| ())
+}
+
+/// Process-lifetime counters. They hold no URLs, names or ids, only counts.
+#[derive(Debug, Default)]
+struct Metrics {
+    requests: u64,
+    processed: u64,
+    successes: u64,
+    failures: BTreeMap<&'static str, u64>,
+    dedupe_lookups: u64,
+    dedupe_hits: u64,
+    total_latency_ms: u128,
+}
+
+/// Records the outcome and duration of one processed add.
+pub fn record_request(result: &Result<String, GastropathError>, latency_ms: u128) {
+    let mut metrics = METRICS.lock().unwrap();
+    metrics.requests += 1;
+    metrics.processed += 1;
+    metrics.total_latency_ms += latency_ms;
+    export("requests", 1, "c");
+    export("latency_ms", latency_ms, "ms");
+    match result {
+        Ok(_) => {
+            metrics.successes += 1;
+            export("successes", 1, "c");
+        }
+        Err(e) => {
+            let category = failure_category(e);
+            *metrics.failures.entry(category).or_insert(0) += 1;
+            export(&format!("failures.{}", category), 1, "c");
+        }
+    }
+}
+
+/// Records a request rejected before processing (bad body or URL).
+pub fn record_rejected() {
+    let mut metrics = METRICS.lock().unwrap();
+    metrics.requests += 1;
+    *metrics.failures.entry("validation").or_insert(0) += 1;
+    export("requests", 1, "c");
+    export("failures.validation", 1, "c");
+}
+
+pub fn record_dedupe_lookup(hit: bool) {
+    let mut metrics = METRICS.lock().unwrap();
+    metrics.dedupe_lookups += 1;
+    export("dedupe_lookups", 1, "c");
+    if hit {
+        metrics.dedupe_hits += 1;
+        export("dedupe_hits", 1, "c");
+    }
+}
+
+fn failure_category(error: &GastropathError) -> &'static str {
+    match error {
+        GastropathError::Validation(_) => "validation",
+        GastropathError::SuspiciousName(_) => "suspicious_name",
+        GastropathError::Ambiguous(_) => "ambiguous",
+        GastropathError::NotFound(_) => "not_found",
+        GastropathError::Expansion(_) => "expansion",
+        GastropathError::GooglePlaces(_) => "google",
+        GastropathError::QuotaExceeded(_) => "quota",
+        GastropathError::PhotoTooSmall(_) => "photo_too_small",
+        GastropathError::Yelp(_) => "yelp",
+        GastropathError::Cloudinary(_) => "cloudinary",
+        GastropathError::Notion(_) => "notion",
+        GastropathError::Http(_) => "http",
+        GastropathError::Other(_) => "other",
+    }
+}
+
+/// Logs a one-line summary of everything counted so far.
+pub fn log_summary() {
+    let metrics = METRICS.lock().unwrap();
+    let failures: Vec<String> = metrics.failures.iter().map(|(category, count)| format!("{}={}", category, count)).collect();
+    let average_latency = if metrics.processed > 0 { metrics.total_latency_ms / metrics.processed as u128 } else { 0 };
+    let hit_rate = if metrics.dedupe_lookups > 0 { 100.0 * metrics.dedupe_hits as f64 / metrics.dedupe_lookups as f64 } else { 0.0 };
+
+    info!(
+        "Metrics summary: requests={} successes={} failures=[{}] dedupe_hit_rate={:.1}% avg_latency={}ms",
+        metrics.requests,
+        metrics.successes,
+        failures.join(" "),
+        hit_rate,
+        average_latency
+    );
+}
+
+/// Logs the summary every `METRICS_SUMMARY_INTERVAL_SECS` seconds, when set.
+pub fn spawn_periodic_summary() {
+    let interval = match env::var("METRICS_SUMMARY_INTERVAL_SECS").ok().and_then(|v| v.parse::<u64>().ok()) {
+        Some(secs) if secs > 0 => Duration::from_secs(secs),
+        _ => return,
+    };
+    actix_web::rt::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            log_summary();
+        }
+    });
+}
@@ -1,77 +1,1448 @@
 use reqwest::Client;
 use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
 use std::env;
-use log::{info, error, debug};
+use crate::rate_limit::TrackedSend;
+use crate::utils::{Provider, RequestBuilderExt};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use log::{info, error, debug, warn};
+use crate::google_places::PhotoAttribution;
 use crate::RestaurantDetails;
+use crate::dedupe::KeyedLocks;
 
+const DEFAULT_INDEX_REFRESH_SECS: u64 = 600;
+/// Maximum number of children blocks Notion accepts in a single request.
+const MAX_BLOCKS_PER_REQUEST: usize = 100;
+/// Pages of query results scanned when looking for an existing entry.
+const MAX_LOOKUP_PAGES: usize = 10;
+
+lazy_static::lazy_static! {
+    /// Property types detected from the database schema, keyed by property name.
+    static ref DETECTED_PROPERTY_TYPES: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+    /// City name -> page id in the `NOTION_CITIES_DB` database.
+    static ref CITY_PAGES: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+    /// One lock per restaurant slug being written, see [`create_or_update_entry`].
+    static ref ENTRY_LOCKS: KeyedLocks = KeyedLocks::default();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WriteMode {
+    Upsert,
+    CreateOnly,
+    UpdateOnly,
+}
+
+impl WriteMode {
+    fn from_env() -> Self {
+        match env::var("NOTION_WRITE_MODE").unwrap_or_default().to_lowercase().as_str() {
+            "" | "upsert" => WriteMode::Upsert,
+            "create_only" => WriteMode::CreateOnly,
+            "update_only" => WriteMode::UpdateOnly,
+            other => {
+                warn!("Unknown NOTION_WRITE_MODE '{}', falling back to upsert", other);
+                WriteMode::Upsert
+            }
+        }
+    }
+}
+
+/// Names of the core properties, each overridable with its `NOTION_PROP_*` variable for
+/// databases whose columns differ from the template (e.g. in another language).
+struct NotionPropertyMap {
+    city: String,
+    country: String,
+    cuisine: String,
+    google_maps: String,
+    price_range: String,
+    website: String,
+}
+
+/// Each `NOTION_PROP_*` variable with the template's property name.
+const PROPERTY_NAME_VARIABLES: [(&str, &str); 6] = [
+    ("NOTION_PROP_CITY", "City"),
+    ("NOTION_PROP_COUNTRY", "Country"),
+    ("NOTION_PROP_CUISINE", "Cuisine Type"),
+    ("NOTION_PROP_GOOGLE_MAPS", "Google Maps"),
+    ("NOTION_PROP_PRICE_RANGE", "Price range"),
+    ("NOTION_PROP_WEBSITE", "Website"),
+];
+
+impl NotionPropertyMap {
+    fn from_env() -> Self {
+        let [city, country, cuisine, google_maps, price_range, website] =
+            PROPERTY_NAME_VARIABLES.map(|(variable, default)| env::var(variable).unwrap_or_else(|_| default.to_string()));
+        NotionPropertyMap { city, country, cuisine, google_maps, price_range, website }
+    }
+}
+
+/// Checks at startup that no `NOTION_PROP_*` variable is set to an empty name.
+pub fn validate_property_map() -> Result<(), String> {
+    match PROPERTY_NAME_VARIABLES.iter().find(|(variable, _)| env::var(variable).is_ok_and(|name| name.trim().is_empty())) {
+        Some((variable, default)) => Err(format!("{} must name a property (unset it to use \"{}\")", variable, default)),
+        None => Ok(()),
+    }
+}
+
+/// In-memory snapshot of the database titles and place_ids, used by batch imports to
+/// avoid one Notion query per item. Only names that match loosely still go through
+/// `find_existing_entry`.
+pub struct EntryIndex {
+    entries: Mutex<IndexedEntries>,
+}
+
+#[derive(Default)]
+struct IndexedEntries {
+    /// Page ids by exact title, `None` when several pages share the title.
+    exact: HashMap<String, Option<String>>,
+    normalized: HashSet<String>,
+    /// Page ids by the value of the place_id property, when there is one.
+    place_ids: HashMap<String, String>,
+    /// Pages that have a place_id stored.
+    with_place_id: HashSet<String>,
+    loaded_at: Option<Instant>,
+}
+
+impl IndexedEntries {
+    fn add(&mut self, title: String, page_id: Option<String>, place_id: Option<String>) {
+        if let (Some(place_id), Some(page_id)) = (place_id, &page_id) {
+            self.place_ids.insert(place_id, page_id.clone());
+            self.with_place_id.insert(page_id.clone());
+        }
+        self.normalized.insert(normalize_name(&title));
+        match self.exact.get_mut(&title) {
+            Some(existing) => *existing = None,
+            None => {
+                self.exact.insert(title, page_id);
+            }
+        }
+    }
+}
+
+enum IndexLookup {
+    Found(String),
+    Missing,
+    Ambiguous,
+}
+
+impl EntryIndex {
+    /// Returns an index when `NOTION_PRELOAD_EXISTING` is enabled, `None` otherwise.
+    pub async fn load_if_enabled(client: &Client) -> Option<EntryIndex> {
+        if !env::var("NOTION_PRELOAD_EXISTING").map(|v| v == "true" || v == "1").unwrap_or(false) {
+            return None;
+        }
+
+        let index = EntryIndex { entries: Mutex::new(IndexedEntries::default()) };
+        match index.reload(client).await {
+            Ok(()) => Some(index),
+            Err(e) => {
+                warn!("Failed to preload existing Notion entries, falling back to per-item queries: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Reloads the index if it is older than `NOTION_INDEX_REFRESH_SECS`.
+    pub async fn refresh_if_stale(&self, client: &Client) {
+        let max_age = Duration::from_secs(
+            env::var("NOTION_INDEX_REFRESH_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_INDEX_REFRESH_SECS),
+        );
+        let stale = self.entries.lock().unwrap().loaded_at.is_none_or(|t| t.elapsed() >= max_age);
+        if stale {
+            if let Err(e) = self.reload(client).await {
+                warn!("Failed to refresh the Notion entry index, keeping the previous one: {}", e);
+            }
+        }
+    }
+
+    async fn reload(&self, client: &Client) -> Result<(), String> {
+        let api_key = env::var("NOTION_API_KEY").map_err(|e| e.to_string())?;
+        let database_id = env::var("NOTION_DATABASE_ID").map_err(|e| e.to_string())?;
+        let url = format!("{}/v1/databases/{}/query", crate::utils::api_base(Provider::Notion), database_id);
+        let title_property = title_property(client, &api_key, &database_id).await;
+        let place_id_property = place_id_property(client, &api_key, &database_id).await;
+
+        let mut loaded = IndexedEntries::default();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut query = json!({ "page_size": 100 });
+            if let Some(cursor) = &cursor {
+                query["start_cursor"] = json!(cursor);
+            }
+
+            let response = client.post(&url)
+                .provider_timeout("NOTION")
+                .header("Authorization", format!("Bearer {}", api_key))
+                .header("Notion-Version", "2022-06-28")
+                .json(&query)
+                .send_tracked("NOTION")
+                .await
+                .map_err(|e| e.to_string())?
+                .json::<Value>()
+                .await
+                .map_err(|e| e.to_string())?;
+
+            for page in response["results"].as_array().into_iter().flatten() {
+                let title: String = page["properties"][&title_property]["title"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|t| t["plain_text"].as_str())
+                    .collect();
+                let place_id = place_id_property.as_ref().and_then(|p| page_place_id(page, p));
+                loaded.add(title, page["id"].as_str().map(String::from), place_id);
+            }
+
+            match response["next_cursor"].as_str() {
+                Some(next) if response["has_more"].as_bool() == Some(true) => cursor = Some(next.to_string()),
+                _ => break,
+            }
+        }
+
+        info!("Loaded {} existing Notion entries into the index", loaded.exact.len());
+        loaded.loaded_at = Some(Instant::now());
+        *self.entries.lock().unwrap() = loaded;
+        Ok(())
+    }
+
+    /// Matches by place_id first, so renamed entries are still found, then by name. A
+    /// page with a different place_id stored is another place with the same name.
+    fn lookup(&self, name: &str, place_id: Option<&str>) -> IndexLookup {
+        let entries = self.entries.lock().unwrap();
+        if let Some(page_id) = place_id.and_then(|place_id| entries.place_ids.get(place_id)) {
+            return IndexLookup::Found(page_id.clone());
+        }
+        match entries.exact.get(name) {
+            Some(Some(page_id)) if place_id.is_some() && entries.with_place_id.contains(page_id) => IndexLookup::Missing,
+            Some(Some(page_id)) => IndexLookup::Found(page_id.clone()),
+            Some(None) => IndexLookup::Ambiguous,
+            None if entries.normalized.contains(&normalize_name(name)) => IndexLookup::Ambiguous,
+            None => IndexLookup::Missing,
+        }
+    }
+
+    /// Records a page created during the batch.
+    fn insert(&self, name: &str, place_id: Option<&str>, page_id: &str) {
+        self.entries.lock().unwrap().add(name.to_string(), Some(page_id.to_string()), place_id.map(String::from));
+    }
+}
+
+/// The place_id stored in a queried page's `property`, as text or URL.
+fn page_place_id(page: &Value, property: &str) -> Option<String> {
+    let value = &page["properties"][property];
+    let place_id = match value["url"].as_str() {
+        Some(url) => url.to_string(),
+        None => value["rich_text"].as_array()?.iter().filter_map(|t| t["plain_text"].as_str()).collect(),
+    };
+    Some(place_id).filter(|p| !p.is_empty())
+}
+
+/// Where a page's cover (and photo block) image lives.
+pub enum Cover {
+    /// Hosted elsewhere (Cloudinary) and referenced by URL.
+    External(String),
+    /// Uploaded to Notion through the file upload API, referenced by upload id.
+    Uploaded(String),
+}
+
+impl Cover {
+    fn file_object(&self) -> Value {
+        match self {
+            Cover::External(url) => json!({"type": "external", "external": {"url": url}}),
+            Cover::Uploaded(id) => json!({"type": "file_upload", "file_upload": {"id": id}}),
+        }
+    }
+}
+
+/// Uploads a file to Notion in a single part and returns the file upload id,
+/// which can then be attached as a cover or image block.
+pub async fn upload_file(client: &Client, bytes: Vec<u8>, filename: &str, content_type: &str) -> Result<String, String> {
+    let api_key = env::var("NOTION_API_KEY").map_err(|e| e.to_string())?;
+
+    let response = client.post(format!("{}/v1/file_uploads", crate::utils::api_base(Provider::Notion)))
+        .provider_timeout("NOTION")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Notion-Version", "2022-06-28")
+        .json(&json!({ "filename": filename, "content_type": content_type }))
+        .send_tracked("NOTION")
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        let error_body = response.text().await.map_err(|e| e.to_string())?;
+        return Err(format!("Failed to create Notion file upload: {}", error_body));
+    }
+    let upload: Value = response.json().await.map_err(|e| e.to_string())?;
+    let upload_id = upload["id"].as_str().ok_or("Notion file upload has no id")?.to_string();
+
+    let part = reqwest::multipart::Part::bytes(bytes)
+        .file_name(filename.to_string())
+        .mime_str(content_type)
+        .map_err(|e| e.to_string())?;
+    let response = client.post(format!("{}/v1/file_uploads/{}/send", crate::utils::api_base(Provider::Notion), upload_id))
+        .provider_timeout("NOTION")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Notion-Version", "2022-06-28")
+        .multipart(reqwest::multipart::Form::new().part("file", part))
+        .send_tracked("NOTION")
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        let error_body = response.text().await.map_err(|e| e.to_string())?;
+        return Err(format!("Failed to send file to Notion: {}", error_body));
+    }
+
+    info!("Uploaded {} to Notion as {}", filename, upload_id);
+    Ok(upload_id)
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Finds the page for this restaurant: through the preloaded index when it gives a
+/// definite answer, otherwise by place_id and slug (if configured) and then by name.
+async fn find_existing_page(
+    client: &Client,
+    api_key: &str,
+    database_id: &str,
+    details: &RestaurantDetails,
+    index: Option<&EntryIndex>,
+) -> Result<Option<String>, String> {
+    Ok(match index.map(|index| index.lookup(&details.name, details.place_id.as_deref())) {
+        Some(IndexLookup::Found(page_id)) => Some(page_id),
+        Some(IndexLookup::Missing) => None,
+        Some(IndexLookup::Ambiguous) | None => {
+            let by_place_id = match (place_id_property(client, api_key, database_id).await, &details.place_id) {
+                (Some(property), Some(place_id)) => find_existing_by_place_id(client, api_key, database_id, &property, place_id).await?,
+                _ => None,
+            };
+            let by_slug = match (&by_place_id, env::var("NOTION_SLUG_PROPERTY")) {
+                (None, Ok(property)) if !property.is_empty() => {
+                    find_existing_by_property(client, api_key, database_id, &property, "rich_text", &details.slug).await?
+                }
+                _ => None,
+            };
+            match by_place_id.or(by_slug) {
+                Some(page_id) => Some(page_id),
+                None => find_existing_entry(client, api_key, database_id, &details.name).await?,
+            }
+        }
+    })
+}
+
+/// Properties written for a restaurant, before the optional city relation.
+async fn entry_properties(
+    client: &Client,
+    api_key: &str,
+    database_id: &str,
+    names: &NotionPropertyMap,
+    details: &RestaurantDetails,
+) -> Result<Value, String> {
+    let mut properties = json!({});
+    properties[&names.cuisine] = cuisine_property(client, api_key, database_id, &names.cuisine, &details.cuisine_type).await;
+    properties[&names.google_maps] = json!({ "url": details.google_maps_link });
+    properties[&names.website] = json!({ "url": details.website });
+    properties[title_property(client, api_key, database_id).await] =
+        json!({ "title": [{"text": {"content": details.name}}] });
+
+    // An empty DEFAULT_CITY/DEFAULT_COUNTRY means the property is left out entirely
+    if !details.city.is_empty() {
+        properties[&names.city] = json!({ "rich_text": [{"text": {"content": details.city}}] });
+    }
+    if !details.country.is_empty() {
+        properties[&names.country] = json!({ "rich_text": [{"text": {"content": details.country}}] });
+    }
+    // Unknown price with PRICE_UNKNOWN=omit
+    if !details.price_level.is_empty() {
+        properties[&names.price_range] = json!({ "select": {"name": details.price_level} });
+    }
+
+    if let Ok(property) = env::var("NOTION_SLUG_PROPERTY") {
+        if !property.is_empty() {
+            properties[property] = json!({ "rich_text": [{"text": {"content": details.slug}}] });
+        }
+    }
+
+    if let (Some(property), Some(place_id)) = (place_id_property(client, api_key, database_id).await, &details.place_id) {
+        properties[&property] = match property_type(client, api_key, database_id, &property).await.as_deref() {
+            Some("url") => json!({ "url": place_id }),
+            _ => json!({ "rich_text": [{"text": {"content": place_id}}] }),
+        };
+    }
+
+    if let (Ok(property), Some(neighborhood)) = (env::var("NOTION_NEIGHBORHOOD_PROPERTY"), &details.neighborhood) {
+        if !property.is_empty() {
+            properties[property] = json!({ "rich_text": [{"text": {"content": neighborhood}}] });
+        }
+    }
+
+    if let (Ok(property), Some(note)) = (env::var("NOTION_NOTES_PROPERTY"), &details.note) {
+        if !property.is_empty() {
+            properties[property] = json!({ "rich_text": [{"text": {"content": note}}] });
+        }
+    }
+
+    if let (Ok(property), Some(timezone)) = (env::var("NOTION_TIMEZONE_PROPERTY"), &details.timezone) {
+        if !property.is_empty() {
+            properties[property] = json!({ "rich_text": [{"text": {"content": timezone}}] });
+        }
+    }
+
+    if let (Ok(property), Some(place_id)) = (env::var("NOTION_DIRECTIONS_PROPERTY"), &details.place_id) {
+        if !property.is_empty() {
+            properties[property] = json!({ "url": directions_url(&details.address, place_id) });
+        }
+    }
+
+    if let (Ok(property), Some(count)) = (env::var("NOTION_PHOTO_COUNT_PROPERTY"), details.photo_count) {
+        if !property.is_empty() {
+            properties[property] = json!({ "number": count });
+        }
+    }
+
+    if let (Some(property), Some(rating)) = (detected_property(client, api_key, database_id, "NOTION_RATING_PROPERTY", "Rating", "number").await, details.rating) {
+        properties[property] = json!({ "number": rating });
+    }
+
+    if let (Some(property), Some(phone)) = (detected_property(client, api_key, database_id, "NOTION_PHONE_PROPERTY", "Phone", "phone_number").await, &details.phone) {
+        properties[property] = json!({ "phone_number": phone });
+    }
+
+    if !details.opening_hours.is_empty() {
+        if let Some(property) = detected_property(client, api_key, database_id, "NOTION_OPENING_HOURS_PROPERTY", "Opening Hours", "rich_text").await {
+            properties[property] = json!({ "rich_text": [{"text": {"content": details.opening_hours.join("\n")}}] });
+        }
+    }
+
+    if let (Ok(property), Some(count)) = (env::var("NOTION_RATING_COUNT_PROPERTY"), details.rating_count) {
+        if !property.is_empty() {
+            properties[property] = json!({ "number": count });
+        }
+    }
+
+    if let (Ok(property), Some(rating)) = (env::var("NOTION_YELP_RATING_PROPERTY"), details.yelp_rating) {
+        if !property.is_empty() {
+            properties[property] = json!({ "number": rating });
+        }
+    }
+
+    if let Ok(property) = env::var("NOTION_RATING_TIER_PROPERTY") {
+        if !property.is_empty() {
+            properties[property] = match rating_tier(details.rating, details.rating_count)? {
+                Some(tier) => json!({ "select": { "name": tier } }),
+                None => json!({ "select": null }),
+            };
+        }
+    }
+
+    if let Ok(property) = env::var("NOTION_MISSING_DATA_PROPERTY") {
+        if !property.is_empty() {
+            let tags = missing_data(details);
+            properties[property] = json!({ "multi_select": tags.iter().map(|name| json!({"name": name})).collect::<Vec<_>>() });
+        }
+    }
+
+    apply_declared_types(properties)
+}
+
+/// Notion property types a value can be coerced to through `NOTION_PROPERTY_TYPES`.
+const PROPERTY_TYPES: [&str; 9] = ["title", "rich_text", "url", "select", "multi_select", "number", "date", "checkbox", "phone_number"];
+
+/// Parses `NOTION_PROPERTY_TYPES`, e.g. `Name=title,City=rich_text,Cuisine Type=multi_select`.
+/// Returns `None` when no types are declared.
+fn declared_property_types() -> Result<Option<HashMap<String, String>>, String> {
+    let raw = match env::var("NOTION_PROPERTY_TYPES") {
+        Ok(raw) if !raw.trim().is_empty() => raw,
+        _ => return Ok(None),
+    };
+
+    let mut types = HashMap::new();
+    for entry in raw.split(',') {
+        let (property, kind) = entry
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Invalid NOTION_PROPERTY_TYPES entry '{}', expected property=type", entry))?;
+        let kind = kind.trim();
+        if !PROPERTY_TYPES.contains(&kind) {
+            return Err(format!("Invalid NOTION_PROPERTY_TYPES type '{}' for {}, expected one of {}", kind, property.trim(), PROPERTY_TYPES.join(", ")));
+        }
+        types.insert(property.trim().to_string(), kind.to_string());
+    }
+
+    Ok(Some(types))
+}
+
+/// Variables naming optional properties that go through [`apply_declared_types`].
+const OPTIONAL_PROPERTY_VARIABLES: [&str; 14] = [
+    "NOTION_SLUG_PROPERTY",
+    "NOTION_PLACE_ID_PROPERTY",
+    "NOTION_NEIGHBORHOOD_PROPERTY",
+    "NOTION_NOTES_PROPERTY",
+    "NOTION_TIMEZONE_PROPERTY",
+    "NOTION_DIRECTIONS_PROPERTY",
+    "NOTION_PHOTO_COUNT_PROPERTY",
+    "NOTION_RATING_PROPERTY",
+    "NOTION_PHONE_PROPERTY",
+    "NOTION_OPENING_HOURS_PROPERTY",
+    "NOTION_RATING_COUNT_PROPERTY",
+    "NOTION_YELP_RATING_PROPERTY",
+    "NOTION_RATING_TIER_PROPERTY",
+    "NOTION_MISSING_DATA_PROPERTY",
+];
+
+/// Checks at startup that `NOTION_PROPERTY_TYPES`, if set, only uses known types and
+/// declares a title plus every property the current configuration writes.
+pub fn validate_property_types() -> Result<(), String> {
+    let types = match declared_property_types()? {
+        Some(types) => types,
+        None => return Ok(()),
+    };
+
+    let names = NotionPropertyMap::from_env();
+    let mut undeclared: Vec<String> = [names.city, names.country, names.cuisine, names.google_maps, names.price_range, names.website]
+        .into_iter()
+        .chain(OPTIONAL_PROPERTY_VARIABLES.iter().filter_map(|variable| env::var(variable).ok().filter(|p| !p.is_empty())))
+        .filter(|property| !types.contains_key(property))
+        .collect();
+    if !types.values().any(|kind| kind == "title") {
+        undeclared.insert(0, "the title property".to_string());
+    }
+
+    if undeclared.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("NOTION_PROPERTY_TYPES has no type for: {}", undeclared.join(", ")))
+    }
+}
+
+/// Whether `property` may be written under `NOTION_PROPERTY_TYPES`: always when no types
+/// are declared, otherwise only when it has a declared type.
+fn is_declared(property: &str) -> bool {
+    declared_property_types().ok().flatten().is_none_or(|types| types.contains_key(property))
+}
+
+/// When `NOTION_PROPERTY_TYPES` is set, rewrites every property to its declared type.
+/// A property without a declared type is an error rather than a guess.
+fn apply_declared_types(properties: Value) -> Result<Value, String> {
+    let types = match declared_property_types()? {
+        Some(types) => types,
+        None => return Ok(properties),
+    };
+
+    let mut typed = json!({});
+    let mut undeclared = Vec::new();
+    for (property, value) in properties.as_object().into_iter().flatten() {
+        match types.get(property) {
+            Some(kind) => typed[property] = coerce_property(kind, property_text(value).unwrap_or_default().as_str()),
+            None => undeclared.push(property.as_str()),
+        }
+    }
+
+    if !undeclared.is_empty() {
+        return Err(format!("NOTION_PROPERTY_TYPES has no type for: {}", undeclared.join(", ")));
+    }
+    Ok(typed)
+}
+
+/// Formats text as a Notion property value of the given type. Values that don't fit
+/// the type (an invalid URL, a non-numeric number...) are written as empty.
+fn coerce_property(kind: &str, text: &str) -> Value {
+    let text = text.trim();
+    match kind {
+        "title" => json!({ "title": [{"text": {"content": text}}] }),
+        "url" => json!({ "url": (text.starts_with("http://") || text.starts_with("https://")).then_some(text) }),
+        // Select options can't contain commas, so only the first of a list is kept
+        "select" => match text.split(',').next().map(str::trim).filter(|t| !t.is_empty()) {
+            Some(name) => json!({ "select": { "name": name } }),
+            None => json!({ "select": null }),
+        },
+        "multi_select" => json!({
+            "multi_select": text.split(',').map(str::trim).filter(|t| !t.is_empty()).map(|name| json!({ "name": name })).collect::<Vec<_>>()
+        }),
+        "number" => json!({ "number": text.parse::<f64>().ok() }),
+        "date" => match chrono::NaiveDate::parse_from_str(text.get(..10).unwrap_or(text), "%Y-%m-%d") {
+            Ok(_) => json!({ "date": { "start": text } }),
+            Err(_) => json!({ "date": null }),
+        },
+        "checkbox" => json!({ "checkbox": !matches!(text.to_lowercase().as_str(), "" | "false" | "0" | "no") }),
+        "phone_number" => json!({ "phone_number": (!text.is_empty()).then_some(text) }),
+        _ => json!({ "rich_text": [{"text": {"content": text}}] }),
+    }
+}
+
+/// Writes are serialized per restaurant (by slug, so by place_id when known): two
+/// concurrent requests for the same place would otherwise both miss the existing entry
+/// and create duplicates. Different places are written in parallel.
 pub async fn create_or_update_entry(
     client: &Client,
     details: RestaurantDetails,
-    cover_url: Option<String>,
+    cover: Option<Cover>,
+    children: Vec<Value>,
+    index: Option<&EntryIndex>,
+) -> Result<String, String> {
+    let _guard = ENTRY_LOCKS.lock(&details.slug).await;
+    write_entry(client, details, cover, children, index).await
+}
+
+async fn write_entry(
+    client: &Client,
+    details: RestaurantDetails,
+    cover: Option<Cover>,
+    children: Vec<Value>,
+    index: Option<&EntryIndex>,
 ) -> Result<String, String> {
     info!("Creating or updating Notion entry for: {}", details.name);
     let api_key = env::var("NOTION_API_KEY").map_err(|e| e.to_string())?;
     let database_id = env::var("NOTION_DATABASE_ID").map_err(|e| e.to_string())?;
+    let write_mode = WriteMode::from_env();
+
+    let names = NotionPropertyMap::from_env();
+
+    let existing_entry = find_existing_page(client, &api_key, &database_id, &details, index).await?;
+    let mut properties = entry_properties(client, &api_key, &database_id, &names, &details).await?;
 
-    let existing_entry = find_existing_entry(client, &api_key, &database_id, &details.name).await?;
+    if let Ok(cities_db) = env::var("NOTION_CITIES_DB") {
+        if !cities_db.is_empty() && !details.city.is_empty() && details.city != "No city available" {
+            match find_or_create_city(client, &api_key, &cities_db, &details.city).await {
+                Ok(city_page_id) => properties[&names.city] = json!({ "relation": [{"id": city_page_id}] }),
+                Err(e) => {
+                    warn!("Failed to relate {} to city {}, leaving {} empty: {}", details.name, details.city, names.city, e);
+                    properties.as_object_mut().unwrap().remove(&names.city);
+                }
+            }
+        }
+    }
+
+    // Notion-hosted uploads have no stable URL to store, only external covers do
+    if let (Ok(property), Some(Cover::External(url))) = (env::var("NOTION_COVER_URL_PROPERTY"), &cover) {
+        if !property.is_empty() {
+            properties[property] = json!({ "url": url });
+        }
+    }
+
+    let cover = cover.map(|cover| cover.file_object());
+
+    match (existing_entry, write_mode) {
+        (Some(_), WriteMode::CreateOnly) => {
+            info!("{} already exists, skipping (create_only mode)", details.name);
+            Ok("Skipped, restaurant already exists in Gastropath".to_string())
+        }
+        (Some(page_id), _) => {
+            let page = match fetch_page(client, &api_key, &page_id).await {
+                Ok(page) => page,
+                Err(e) => {
+                    warn!("Failed to read the existing entry for {}, updating every property: {}", details.name, e);
+                    keep_known_numbers(&mut properties);
+                    stamp_dates(&mut properties, false);
+                    return update_page(client, &api_key, &page_id, properties, cover).await;
+                }
+            };
+            resolve_location_conflicts(&page, &mut properties, &names, &details.name);
+            keep_known_numbers(&mut properties);
+            let mut properties = changed_properties(&page, properties);
+            let cover = cover.filter(|cover| cover["type"] != "external" || page["cover"]["external"]["url"] != cover["external"]["url"]);
+            let changed: Vec<String> = properties.as_object().map(|p| p.keys().cloned().collect()).unwrap_or_default();
+            stamp_dates(&mut properties, false);
+            if changed.is_empty() && cover.is_none() {
+                if properties.as_object().is_some_and(|p| !p.is_empty()) {
+                    info!("{} is already up to date, only refreshing its sync date", details.name);
+                    update_page(client, &api_key, &page_id, properties, None).await?;
+                } else {
+                    info!("{} is already up to date, nothing to write", details.name);
+                }
+                return Ok("Restaurant already up to date in Gastropath".to_string());
+            }
+            info!("Updating {} - changed properties: {:?}, cover changed: {}", details.name, changed, cover.is_some());
+            update_page(client, &api_key, &page_id, properties, cover).await
+        }
+        (None, WriteMode::UpdateOnly) => {
+            error!("{} not found in Notion and update_only mode forbids creating it", details.name);
+            Err("Restaurant not found in Gastropath (update_only mode)".to_string())
+        }
+        (None, _) => {
+            stamp_dates(&mut properties, true);
+            let page_id = create_page(client, &api_key, &database_id, properties, page_icon(&details), cover, children).await?;
+            if let Some(index) = index {
+                index.insert(&details.name, details.place_id.as_deref(), &page_id);
+            }
+            crate::post_process::entry_created(client, &details, &page_id);
+            Ok("Restaurant successfully added to Gastropath".to_string())
+        }
+    }
+}
+
+/// With `KEEP_ON_UNKNOWN=true`, drops number properties that would be cleared on
+/// update (a price or rating that became unknown), so the stored value is kept.
+fn keep_known_numbers(properties: &mut Value) {
+    if !env::var("KEEP_ON_UNKNOWN").map(|v| v == "true" || v == "1").unwrap_or(false) {
+        return;
+    }
+    if let Some(properties) = properties.as_object_mut() {
+        properties.retain(|property, value| {
+            let unknown = value.get("number").is_some_and(Value::is_null);
+            if unknown {
+                info!("Keeping the stored {}, the new value is unknown", property);
+            }
+            !unknown
+        });
+    }
+}
+
+/// Sets the `NOTION_LAST_SYNCED_PROPERTY` date to now (UTC) on every write, and the
+/// `NOTION_ADDED_DATE_PROPERTY` date only when the entry is created.
+fn stamp_dates(properties: &mut Value, created: bool) {
+    let now = chrono::Utc::now();
+    if let Ok(property) = env::var("NOTION_LAST_SYNCED_PROPERTY") {
+        if !property.is_empty() {
+            properties[property] = json!({ "date": { "start": now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true) } });
+        }
+    }
+    if let Ok(property) = env::var("NOTION_ADDED_DATE_PROPERTY") {
+        if created && !property.is_empty() {
+            properties[property] = json!({ "date": { "start": now.format("%Y-%m-%d").to_string() } });
+        }
+    }
+}
+
+/// `NOTION_PLACE_ID_PROPERTY` when set (empty to not store place_ids), otherwise `Place ID`
+/// if the database has a text or URL property by that name.
+async fn place_id_property(client: &Client, api_key: &str, database_id: &str) -> Option<String> {
+    match env::var("NOTION_PLACE_ID_PROPERTY") {
+        Ok(property) => Some(property).filter(|p| !p.is_empty()),
+        Err(_) if !is_declared("Place ID") => None,
+        Err(_) => matches!(property_type(client, api_key, database_id, "Place ID").await.as_deref(), Some("rich_text" | "url"))
+            .then(|| "Place ID".to_string()),
+    }
+}
 
-    if existing_entry.is_some() {
-        return Ok("Restaurant already in the database".to_string());
+/// The property named by `variable` when set (empty to not write it), otherwise
+/// `default` if the database has a property of that name and type (and, with
+/// `NOTION_PROPERTY_TYPES`, it is declared there).
+async fn detected_property(client: &Client, api_key: &str, database_id: &str, variable: &str, default: &str, kind: &str) -> Option<String> {
+    match env::var(variable) {
+        Ok(property) => Some(property).filter(|p| !p.is_empty()),
+        Err(_) if !is_declared(default) => None,
+        Err(_) => (property_type(client, api_key, database_id, default).await.as_deref() == Some(kind)).then(|| default.to_string()),
     }
+}
+
+/// Whether Google's rating is written anywhere: a rating count or tier property is set,
+/// or the rating property is configured or detected in the database.
+pub async fn wants_rating(client: &Client) -> bool {
+    let is_set = |variable| env::var(variable).is_ok_and(|p| !p.is_empty());
+    if is_set("NOTION_RATING_COUNT_PROPERTY") || is_set("NOTION_RATING_TIER_PROPERTY") {
+        return true;
+    }
+    let (Ok(api_key), Ok(database_id)) = (env::var("NOTION_API_KEY"), env::var("NOTION_DATABASE_ID")) else {
+        return false;
+    };
+    detected_property(client, &api_key, &database_id, "NOTION_RATING_PROPERTY", "Rating", "number").await.is_some()
+}
+
+/// Checks at startup that `RATING_TIERS` parses.
+pub fn validate_rating_tiers() -> Result<(), String> {
+    rating_tier(Some(f64::NEG_INFINITY), Some(0)).map(|_| ())
+}
+
+/// The first tier in `RATING_TIERS` whose minimum rating and review count the place
+/// meets. Tiers are `name=rating:count`, comma-separated and best first; the default is
+/// `Highly rated=4.5:100,Well rated=4.0:20`.
+fn rating_tier(rating: Option<f64>, count: Option<u64>) -> Result<Option<String>, String> {
+    let (rating, count) = match (rating, count) {
+        (Some(rating), count) => (rating, count.unwrap_or(0)),
+        (None, _) => return Ok(None),
+    };
+    let tiers = env::var("RATING_TIERS").unwrap_or_else(|_| "Highly rated=4.5:100,Well rated=4.0:20".to_string());
+    for tier in tiers.split(',').filter(|t| !t.trim().is_empty()) {
+        let invalid = || format!("Invalid RATING_TIERS entry '{}', expected name=rating:count", tier);
+        let (name, thresholds) = tier.rsplit_once('=').ok_or_else(invalid)?;
+        let (min_rating, min_count) = thresholds.split_once(':').ok_or_else(invalid)?;
+        let min_rating = min_rating.trim().parse::<f64>().map_err(|_| invalid())?;
+        let min_count = min_count.trim().parse::<u64>().map_err(|_| invalid())?;
+        if rating >= min_rating && count >= min_count {
+            return Ok(Some(name.trim().to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// A Maps URL that opens navigation to the place. Google wants a `destination` alongside
+/// `destination_place_id`, which wins when both are given.
+fn directions_url(address: &str, place_id: &str) -> String {
+    let encode = |text: &str| url::form_urlencoded::byte_serialize(text.as_bytes()).collect::<String>();
+    format!(
+        "https://www.google.com/maps/dir/?api=1&destination={}&destination_place_id={}",
+        encode(address),
+        encode(place_id)
+    )
+}
+
+/// Emoji for a new page according to `NOTION_ICON_SOURCE`: `fixed` (default, 🍽️),
+/// `cuisine` for the leading emoji of the cuisine, or `country_flag`. Falls back to
+/// 🍽️ when the chosen source has nothing to show.
+fn page_icon(details: &RestaurantDetails) -> String {
+    let icon = match env::var("NOTION_ICON_SOURCE").unwrap_or_default().to_lowercase().as_str() {
+        "cuisine" => {
+            let emoji: String = details.cuisine_type.trim().chars().take_while(|c| !c.is_alphanumeric() && !c.is_whitespace()).collect();
+            Some(emoji).filter(|e| !e.is_empty() && e != "❓")
+        }
+        "country_flag" => details.country_code.as_deref().and_then(flag_emoji),
+        _ => None,
+    };
+    icon.unwrap_or_else(|| "🍽️".to_string())
+}
+
+/// The regional indicator pair for a two-letter country code, e.g. `FR` -> 🇫🇷.
+fn flag_emoji(country_code: &str) -> Option<String> {
+    if country_code.len() != 2 || !country_code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    country_code
+        .to_ascii_uppercase()
+        .chars()
+        .map(|c| char::from_u32(0x1F1E6 + (c as u32 - 'A' as u32)))
+        .collect()
+}
 
-    let url = "https://api.notion.com/v1/pages".to_string();
+/// Tags such as `No photo` for each field in `MISSING_DATA_FIELDS` (comma-separated,
+/// default `photo,cuisine,website,price,city`) that has no real value.
+fn missing_data(details: &RestaurantDetails) -> Vec<String> {
+    let fields = env::var("MISSING_DATA_FIELDS").unwrap_or_else(|_| "photo,cuisine,website,price,city".to_string());
+    let mut tags = Vec::new();
+    for field in fields.split(',').map(|f| f.trim().to_lowercase()) {
+        let missing = match field.as_str() {
+            "photo" => details.photo_reference.is_none(),
+            "cuisine" => details.cuisine_type.is_empty() || details.cuisine_type == "❓",
+            "website" => details.website == "No website available",
+            "price" => details.price_level.is_empty() || details.price_level == crate::google_places::unknown_price(),
+            "city" => details.city.is_empty() || details.city == "No city available" || env::var("DEFAULT_CITY").is_ok_and(|d| d == details.city),
+            "country" => details.country.is_empty() || details.country == "No country available" || env::var("DEFAULT_COUNTRY").is_ok_and(|d| d == details.country),
+            "" => continue,
+            _ => {
+                warn!("Ignoring unknown missing-data field '{}'", field);
+                continue;
+            }
+        };
+        if missing {
+            tags.push(format!("No {}", field));
+        }
+    }
+    tags
+}
+
+/// Builds the cuisine value according to `NOTION_CUISINE_FORMAT`: `rich_text`
+/// (default), `multi_select`, or `auto` to follow the database schema.
+async fn cuisine_property(client: &Client, api_key: &str, database_id: &str, property: &str, cuisine_type: &str) -> Value {
+    let format = match env::var("NOTION_CUISINE_FORMAT").unwrap_or_default().to_lowercase().as_str() {
+        "multi_select" => "multi_select".to_string(),
+        "auto" => property_type(client, api_key, database_id, property)
+            .await
+            .unwrap_or_else(|| "rich_text".to_string()),
+        _ => "rich_text".to_string(),
+    };
+
+    if format != "multi_select" {
+        return json!({ "rich_text": [{"text": {"content": cuisine_type}}] });
+    }
+
+    let options = multi_select_options(cuisine_type, &cuisine_synonyms());
+    json!({ "multi_select": options.iter().map(|name| json!({"name": name})).collect::<Vec<_>>() })
+}
+
+/// Splits a cuisine list into title-cased options, merging synonyms and duplicates
+/// (ignoring any leading emoji).
+fn multi_select_options(cuisine_type: &str, synonyms: &HashMap<String, String>) -> Vec<String> {
+    let mut options: Vec<String> = Vec::new();
+    for category in cuisine_type.split(',').map(title_case).filter(|c| !c.is_empty()) {
+        let category = canonical_cuisine(&category, synonyms);
+        let name = crate::google_places::strip_cuisine_emoji(&category);
+        if !options.iter().any(|o| crate::google_places::strip_cuisine_emoji(o).eq_ignore_ascii_case(name)) {
+            options.push(category);
+        }
+    }
+    options
+}
+
+/// Synonyms merged into one multi-select option before writing.
+const DEFAULT_CUISINE_SYNONYMS: &[(&str, &str)] = &[
+    ("Pizzerias", "Pizza"),
+    ("Pizza Place", "Pizza"),
+    ("Sushi Bars", "Sushi"),
+    ("Coffee & Tea", "Coffee"),
+    ("Coffee Shop", "Coffee"),
+    ("Cafes", "Cafe"),
+    ("Bakeries", "Bakery"),
+    ("Burgers", "Burger"),
+    ("Hamburger", "Burger"),
+    ("Steakhouses", "Steakhouse"),
+    ("Steak House", "Steakhouse"),
+    ("Bars", "Bar"),
+];
+
+/// The built-in synonyms plus `CUISINE_SYNONYMS` (comma-separated `synonym=option`
+/// pairs, which take precedence), keyed by lowercase synonym.
+fn cuisine_synonyms() -> HashMap<String, String> {
+    parse_cuisine_synonyms(&env::var("CUISINE_SYNONYMS").unwrap_or_default())
+}
+
+/// The built-in synonyms, extended or overridden by `synonym=option` pairs.
+fn parse_cuisine_synonyms(raw: &str) -> HashMap<String, String> {
+    let mut synonyms: HashMap<String, String> = DEFAULT_CUISINE_SYNONYMS
+        .iter()
+        .map(|(synonym, option)| (synonym.to_lowercase(), option.to_string()))
+        .collect();
+    for pair in raw.split(',') {
+        match pair.split_once('=') {
+            Some((synonym, option)) if !synonym.trim().is_empty() && !option.trim().is_empty() => {
+                synonyms.insert(synonym.trim().to_lowercase(), title_case(option));
+            }
+            _ if pair.trim().is_empty() => {}
+            _ => warn!("Ignoring invalid CUISINE_SYNONYMS entry '{}'", pair.trim()),
+        }
+    }
+    synonyms
+}
+
+/// Replaces a known synonym with its option, keeping any leading emoji.
+fn canonical_cuisine(category: &str, synonyms: &HashMap<String, String>) -> String {
+    let name = crate::google_places::strip_cuisine_emoji(category);
+    match synonyms.get(&name.to_lowercase()) {
+        Some(option) => {
+            let emoji = &category[..category.len() - category.trim_start_matches(|c: char| !c.is_alphanumeric()).len()];
+            format!("{}{}", emoji, option)
+        }
+        None => category.to_string(),
+    }
+}
+
+fn title_case(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Fetches the property name -> type map of the database, caching it after the first
+/// successful fetch.
+async fn database_schema(client: &Client, api_key: &str, database_id: &str) -> Option<HashMap<String, String>> {
+    {
+        let detected = DETECTED_PROPERTY_TYPES.lock().unwrap();
+        if !detected.is_empty() {
+            return Some(detected.clone());
+        }
+    }
+
+    let url = format!("{}/v1/databases/{}", crate::utils::api_base(Provider::Notion), database_id);
+    let schema = client.get(&url)
+        .provider_timeout("NOTION")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Notion-Version", "2022-06-28")
+        .send_tracked("NOTION")
+        .await
+        .ok()?
+        .json::<Value>()
+        .await
+        .ok()?;
+
+    let mut detected = DETECTED_PROPERTY_TYPES.lock().unwrap();
+    for (name, definition) in schema["properties"].as_object()? {
+        if let Some(kind) = definition["type"].as_str() {
+            if kind == "title" {
+                info!("Detected Notion title property: {}", name);
+            }
+            detected.insert(name.clone(), kind.to_string());
+        }
+    }
+    debug!("Detected Notion property types: {:?}", detected);
+    Some(detected.clone())
+}
+
+async fn property_type(client: &Client, api_key: &str, database_id: &str, property: &str) -> Option<String> {
+    database_schema(client, api_key, database_id).await?.get(property).cloned()
+}
+
+/// Name of the database's title property. Workspaces in other languages don't call
+/// it "Name", so it is detected from the schema, falling back to "Name".
+async fn title_property(client: &Client, api_key: &str, database_id: &str) -> String {
+    database_schema(client, api_key, database_id)
+        .await
+        .and_then(|schema| schema.into_iter().find(|(_, kind)| kind == "title").map(|(name, _)| name))
+        .unwrap_or_else(|| "Name".to_string())
+}
+
+/// Returns the page id of `city` in the Cities database, creating the page if needed.
+async fn find_or_create_city(client: &Client, api_key: &str, cities_db: &str, city: &str) -> Result<String, String> {
+    if let Some(page_id) = CITY_PAGES.lock().unwrap().get(city) {
+        return Ok(page_id.clone());
+    }
+
+    let title_property = env::var("NOTION_CITIES_TITLE_PROPERTY").unwrap_or_else(|_| "Name".to_string());
+
+    let query_url = format!("{}/v1/databases/{}/query", crate::utils::api_base(Provider::Notion), cities_db);
+    let query = json!({
+        "filter": {
+            "property": title_property,
+            "title": { "equals": city }
+        }
+    });
+
+    let response = client.post(&query_url)
+        .provider_timeout("NOTION")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Notion-Version", "2022-06-28")
+        .json(&query)
+        .send_tracked("NOTION")
+        .await
+        .map_err(|e| e.to_string())?
+        .json::<Value>()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let page_id = match response["results"][0]["id"].as_str() {
+        Some(page_id) => page_id.to_string(),
+        None => {
+            info!("Creating city {} in the Cities database", city);
+            let data = json!({
+                "parent": { "database_id": cities_db },
+                "properties": {
+                    title_property: { "title": [{"text": {"content": city}}] }
+                }
+            });
+
+            let created = client.post(format!("{}/v1/pages", crate::utils::api_base(Provider::Notion)))
+                .provider_timeout("NOTION")
+                .header("Authorization", format!("Bearer {}", api_key))
+                .header("Notion-Version", "2022-06-28")
+                .json(&data)
+                .send_tracked("NOTION")
+                .await
+                .map_err(|e| e.to_string())?
+                .json::<Value>()
+                .await
+                .map_err(|e| e.to_string())?;
+
+            created["id"].as_str()
+                .map(String::from)
+                .ok_or_else(|| format!("Failed to create city page: {}", created))?
+        }
+    };
+
+    debug!("City {} has page id {}", city, page_id);
+    CITY_PAGES.lock().unwrap().insert(city.to_string(), page_id.clone());
+    Ok(page_id)
+}
+
+/// Image block for the page body, captioned with the photo's contributor when known.
+pub fn image_block(cover: &Cover, attribution: Option<&PhotoAttribution>) -> Value {
+    let caption = match attribution {
+        Some(attribution) => json!([
+            {"type": "text", "text": {"content": "Photo: "}},
+            {"type": "text", "text": {
+                "content": attribution.name,
+                "link": attribution.link.as_ref().map(|url| json!({"url": url})),
+            }}
+        ]),
+        None => json!([]),
+    };
+
+    let mut image = cover.file_object();
+    image["caption"] = caption;
+    json!({
+        "object": "block",
+        "type": "image",
+        "image": image
+    })
+}
+
+/// Creates the page, returning its id.
+async fn create_page(
+    client: &Client,
+    api_key: &str,
+    database_id: &str,
+    properties: Value,
+    icon: String,
+    cover: Option<Value>,
+    mut children: Vec<Value>,
+) -> Result<String, String> {
+    let url = format!("{}/v1/pages", crate::utils::api_base(Provider::Notion));
 
     debug!("Notion API request URL: {}", url);
 
+    // Send as many blocks as allowed with the page itself so the common case is a
+    // single request; anything beyond the limit is appended afterwards.
+    let remaining_children = children.split_off(children.len().min(MAX_BLOCKS_PER_REQUEST));
+
     let mut data = json!({
         "parent": { "database_id": database_id },
-        "properties": {
-            "City": {
-                "rich_text": [{"text": {"content": details.city}}]
-            },
-            "Country": {
-                "rich_text": [{"text": {"content": details.country}}]
-            },
-            "Cuisine Type": {
-                "rich_text": [{"text": {"content": details.cuisine_type}}]
-            },
-            "Google Maps": {
-                "url": details.google_maps_link
-            },
-            "Price range": {
-                "select": {"name": details.price_level}
-            },
-            "Website": {
-                "url": details.website
-            },
-            "Name": {
-                "title": [{"text": {"content": details.name}}]
-            }
-        },
-        "icon": {"type": "emoji", "emoji": "🍽️"}
+        "properties": properties,
+        "icon": {"type": "emoji", "emoji": icon}
     });
 
-    if let Some(url) = cover_url {
-        data["cover"] = json!({"type": "external", "external": {"url": url}});
+    if !children.is_empty() {
+        data["children"] = json!(children);
+    }
+
+    if let Some(cover) = cover {
+        data["cover"] = cover;
     }
 
     debug!("Notion API request data: {:?}", data);
 
     let response = client.post(&url)
+        .provider_timeout("NOTION")
         .header("Authorization", format!("Bearer {}", api_key))
         .header("Notion-Version", "2022-06-28")
         .json(&data)
-        .send()
+        .send_tracked("NOTION")
         .await
         .map_err(|e| e.to_string())?;
 
     if response.status().is_success() {
-        Ok("Restaurant successfully added to Gastropath".to_string())
+        let page = response.json::<Value>().await.map_err(|e| e.to_string())?;
+        let page_id = page["id"].as_str().unwrap_or_default().to_string();
+        if !remaining_children.is_empty() {
+            if let Err(e) = append_children(client, api_key, &page_id, remaining_children).await {
+                warn!("Page created but appending the remaining blocks failed: {}", e);
+            }
+        }
+        Ok(page_id)
     } else {
         let status = response.status();
         let error_body = response.text().await.map_err(|e| e.to_string())?;
         error!("Failed to create Notion entry. Status: {}, Body: {}", status, error_body);
-        Err("Failed to add restaurant to Gastropath".to_string())
+        match describe_validation_error(&error_body, &data["properties"]) {
+            Some(diagnostic) => Err(format!("Failed to add restaurant to Gastropath: {}", diagnostic)),
+            None => Err("Failed to add restaurant to Gastropath".to_string()),
+        }
+    }
+}
+
+/// A database entry that has no cover image.
+pub struct CoverlessPage {
+    pub page_id: String,
+    pub name: String,
+    pub place_id: Option<String>,
+}
+
+/// Scans the whole database for entries without a cover. The place_id comes from
+/// the place_id property (see [`place_id_property`]), or from a canonical `place_id:` Google Maps link.
+pub async fn pages_without_cover(client: &Client) -> Result<Vec<CoverlessPage>, String> {
+    let api_key = env::var("NOTION_API_KEY").map_err(|e| e.to_string())?;
+    let database_id = env::var("NOTION_DATABASE_ID").map_err(|e| e.to_string())?;
+    let url = format!("{}/v1/databases/{}/query", crate::utils::api_base(Provider::Notion), database_id);
+    let title_property = title_property(client, &api_key, &database_id).await;
+    let place_id_property = place_id_property(client, &api_key, &database_id).await;
+    let google_maps_property = NotionPropertyMap::from_env().google_maps;
+
+    let mut pages = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut query = json!({ "page_size": 100 });
+        if let Some(cursor) = &cursor {
+            query["start_cursor"] = json!(cursor);
+        }
+
+        let response = client.post(&url)
+            .provider_timeout("NOTION")
+            .header("Authorization", format!("Bearer {}", api_key))
+            .header("Notion-Version", "2022-06-28")
+            .json(&query)
+            .send_tracked("NOTION")
+            .await
+            .map_err(|e| e.to_string())?
+            .json::<Value>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        for page in response["results"].as_array().into_iter().flatten() {
+            if !page["cover"].is_null() {
+                continue;
+            }
+            let properties = &page["properties"];
+            let stored_place_id = place_id_property.as_ref().and_then(|p| property_text(&properties[p]));
+            let place_id = stored_place_id.or_else(|| {
+                properties[&google_maps_property]["url"]
+                    .as_str()
+                    .and_then(|link| link.split_once("place_id:"))
+                    .map(|(_, id)| id.split('&').next().unwrap_or(id).to_string())
+            });
+            pages.push(CoverlessPage {
+                page_id: page["id"].as_str().unwrap_or_default().to_string(),
+                name: property_text(&properties[&title_property]).unwrap_or_default(),
+                place_id,
+            });
+        }
+
+        match response["next_cursor"].as_str() {
+            Some(next) if response["has_more"].as_bool() == Some(true) => cursor = Some(next.to_string()),
+            _ => break,
+        }
+    }
+
+    Ok(pages)
+}
+
+/// Sets only the cover of an existing page.
+pub async fn set_cover(client: &Client, page_id: &str, cover: &Cover) -> Result<(), String> {
+    let api_key = env::var("NOTION_API_KEY").map_err(|e| e.to_string())?;
+    let response = client.patch(format!("{}/v1/pages/{}", crate::utils::api_base(Provider::Notion), page_id))
+        .provider_timeout("NOTION")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Notion-Version", "2022-06-28")
+        .json(&json!({ "cover": cover.file_object() }))
+        .send_tracked("NOTION")
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let status = response.status();
+        let error_body = response.text().await.map_err(|e| e.to_string())?;
+        Err(format!("Status: {}, Body: {}", status, error_body))
+    }
+}
+
+/// Compares freshly resolved details with the existing Notion entry without writing
+/// anything. Returns `{"exists": false}` when there is no entry, otherwise the
+/// properties that would change with their old and new values.
+pub async fn diff_entry(client: &Client, details: &RestaurantDetails) -> Result<Value, String> {
+    let api_key = env::var("NOTION_API_KEY").map_err(|e| e.to_string())?;
+    let database_id = env::var("NOTION_DATABASE_ID").map_err(|e| e.to_string())?;
+
+    let page_id = match find_existing_page(client, &api_key, &database_id, details, None).await? {
+        Some(page_id) => page_id,
+        None => return Ok(json!({ "exists": false })),
+    };
+
+    let page = fetch_page(client, &api_key, &page_id).await?;
+    let names = NotionPropertyMap::from_env();
+    let new_properties = entry_properties(client, &api_key, &database_id, &names, details).await?;
+    let mut changes = Vec::new();
+    for (name, new_value) in new_properties.as_object().into_iter().flatten() {
+        let old_value = &page["properties"][name];
+        // Relations (e.g. City pointing at a cities database) hold page ids, not comparable text
+        if old_value.get("relation").is_some() {
+            continue;
+        }
+        let (old, new) = (property_text(old_value), property_text(new_value));
+        if old != new {
+            changes.push(json!({ "property": name, "old": old, "new": new }));
+        }
+    }
+
+    Ok(json!({ "exists": true, "page_id": page_id, "changes": changes }))
+}
+
+async fn fetch_page(client: &Client, api_key: &str, page_id: &str) -> Result<Value, String> {
+    let response = client.get(format!("{}/v1/pages/{}", crate::utils::api_base(Provider::Notion), page_id))
+        .provider_timeout("NOTION")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Notion-Version", "2022-06-28")
+        .send_tracked("NOTION")
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("Status: {}", response.status()));
+    }
+    response.json::<Value>().await.map_err(|e| e.to_string())
+}
+
+/// Handles a City or Country that differs from a non-empty stored value according to
+/// `LOCATION_CONFLICT_MODE`: `overwrite` (default), `keep` the stored value, or `flag` it,
+/// which also keeps the stored value and describes the difference in
+/// `NOTION_LOCATION_CONFLICT_PROPERTY` (cleared when there is none).
+fn resolve_location_conflicts(page: &Value, properties: &mut Value, names: &NotionPropertyMap, name: &str) {
+    let mode = env::var("LOCATION_CONFLICT_MODE").unwrap_or_default().to_lowercase();
+    let mut conflicts = Vec::new();
+    for property in [&names.city, &names.country] {
+        let (old_value, new_value) = match (page["properties"].get(property), properties.get(property)) {
+            (Some(old_value), Some(new_value)) => (old_value, new_value),
+            _ => continue,
+        };
+        // A City relation stores a page id, which can't be compared with the resolved name
+        if old_value.get("relation").is_some() || new_value.get("relation").is_some() {
+            continue;
+        }
+        if let (Some(old), Some(new)) = (property_text(old_value), property_text(new_value)) {
+            if old != new {
+                info!("{} - {} differs from the existing entry: stored {}, resolved {}", name, property, old, new);
+                conflicts.push(format!("{}: {} → {}", property, old, new));
+                if mode == "keep" || mode == "flag" {
+                    properties.as_object_mut().unwrap().remove(property);
+                }
+            }
+        }
+    }
+
+    if mode == "flag" {
+        if let Ok(property) = env::var("NOTION_LOCATION_CONFLICT_PROPERTY") {
+            if !property.is_empty() {
+                properties[property] = json!({ "rich_text": [{"text": {"content": conflicts.join("; ")}}] });
+            }
+        }
+    }
+}
+
+/// Keeps only the properties whose value differs from the existing page, so updates
+/// leave untouched fields (and any manual edits to them) out of the edit history.
+fn changed_properties(page: &Value, properties: Value) -> Value {
+    let relation_ids = |value: &Value| {
+        let mut ids: Vec<String> = value["relation"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r["id"].as_str().map(|id| id.replace('-', "")))
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    let changed = properties
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(name, new_value)| {
+            let old_value = &page["properties"][name.as_str()];
+            if new_value.get("relation").is_some() {
+                relation_ids(old_value) != relation_ids(new_value)
+            } else {
+                property_text(old_value) != property_text(new_value)
+            }
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    Value::Object(changed)
+}
+
+/// Plain text of a property value, either as read from Notion or as built for a write.
+fn property_text(value: &Value) -> Option<String> {
+    let rich_text = |items: &Value| {
+        items.as_array().map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item["plain_text"].as_str().or_else(|| item["text"]["content"].as_str()))
+                .collect::<String>()
+        })
+    };
+
+    let text = if let Some(items) = value.get("title").or_else(|| value.get("rich_text")) {
+        rich_text(items)
+    } else if let Some(url) = value.get("url") {
+        url.as_str().map(String::from)
+    } else if let Some(select) = value.get("select") {
+        select["name"].as_str().map(String::from)
+    } else if let Some(options) = value.get("multi_select") {
+        options.as_array().map(|options| {
+            options.iter().filter_map(|o| o["name"].as_str()).collect::<Vec<_>>().join(", ")
+        })
+    } else if let Some(number) = value.get("number") {
+        number.as_f64().map(|n| n.to_string())
+    } else if let Some(date) = value.get("date") {
+        date["start"].as_str().map(String::from)
+    } else if let Some(checked) = value.get("checkbox") {
+        checked.as_bool().map(|c| c.to_string())
+    } else if let Some(text) = value.get("phone_number").or_else(|| value.get("email")) {
+        text.as_str().map(String::from)
+    } else {
+        None
+    };
+    text.filter(|t| !t.is_empty())
+}
+
+/// Turns a Notion `validation_error` body into a message naming the rejected property
+/// and the value that was sent, e.g. `property "Website" (sent {"url":""}): Website is expected to be url.`
+/// Returns `None` for other errors, or when no sent property is mentioned.
+fn describe_validation_error(error_body: &str, properties: &Value) -> Option<String> {
+    let error: Value = serde_json::from_str(error_body).ok()?;
+    if error["code"].as_str() != Some("validation_error") {
+        return None;
+    }
+    let message = error["message"].as_str()?;
+
+    // Notion words these either as "body.properties.Website.url should be ..." or
+    // "Website is expected to be url.", so look for any property we sent; the
+    // longest match wins to avoid "City" matching inside "City Area".
+    let property = properties
+        .as_object()?
+        .keys()
+        .filter(|name| message.contains(&format!("properties.{}.", name)) || message.starts_with(&format!("{} ", name)))
+        .max_by_key(|name| name.len())?;
+
+    Some(format!("property \"{}\" (sent {}): {}", property, properties[property], message))
+}
+
+async fn append_children(client: &Client, api_key: &str, page_id: &str, children: Vec<Value>) -> Result<(), String> {
+    let url = format!("{}/v1/blocks/{}/children", crate::utils::api_base(Provider::Notion), page_id);
+
+    for chunk in children.chunks(MAX_BLOCKS_PER_REQUEST) {
+        debug!("Appending {} blocks to {}", chunk.len(), page_id);
+        let response = client.patch(&url)
+            .provider_timeout("NOTION")
+            .header("Authorization", format!("Bearer {}", api_key))
+            .header("Notion-Version", "2022-06-28")
+            .json(&json!({ "children": chunk }))
+            .send_tracked("NOTION")
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_body = response.text().await.map_err(|e| e.to_string())?;
+            return Err(format!("Status: {}, Body: {}", status, error_body));
+        }
+    }
+
+    Ok(())
+}
+
+async fn update_page(
+    client: &Client,
+    api_key: &str,
+    page_id: &str,
+    properties: Value,
+    cover: Option<Value>,
+) -> Result<String, String> {
+    let url = format!("{}/v1/pages/{}", crate::utils::api_base(Provider::Notion), page_id);
+
+    debug!("Notion API update URL: {}", url);
+
+    let mut data = json!({ "properties": properties });
+
+    if let Some(cover) = cover {
+        data["cover"] = cover;
+    }
+
+    debug!("Notion API update data: {:?}", data);
+
+    let response = client.patch(&url)
+        .provider_timeout("NOTION")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Notion-Version", "2022-06-28")
+        .json(&data)
+        .send_tracked("NOTION")
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        Ok("Restaurant successfully updated in Gastropath".to_string())
+    } else {
+        let status = response.status();
+        let error_body = response.text().await.map_err(|e| e.to_string())?;
+        error!("Failed to update Notion entry. Status: {}, Body: {}", status, error_body);
+        match describe_validation_error(&error_body, &data["properties"]) {
+            Some(diagnostic) => Err(format!("Failed to update restaurant in Gastropath: {}", diagnostic)),
+            None => Err("Failed to update restaurant in Gastropath".to_string()),
+        }
     }
 }
 
@@ -81,37 +1452,220 @@ async fn find_existing_entry(
     database_id: &str,
     restaurant_name: &str,
 ) -> Result<Option<String>, String> {
-    let url = format!("https://api.notion.com/v1/databases/{}/query", database_id);
+    let url = format!("{}/v1/databases/{}/query", crate::utils::api_base(Provider::Notion), database_id);
     debug!("Querying Notion database: {}", url);
 
-    let query = json!({
-        "filter": {
-            "property": "Name",
-            "title": {
-                "equals": restaurant_name
-            }
+    let filter = json!({
+        "property": title_property(client, api_key, database_id).await,
+        "title": {
+            "equals": restaurant_name
         }
     });
+    first_match(client, api_key, &url, filter).await
+}
 
-    let response = client.post(&url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Notion-Version", "2022-06-28")
-        .json(&query)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?
-        .json::<Value>()
-        .await
-        .map_err(|e| e.to_string())?;
 
-    debug!("Notion query response: {:?}", response);
+/// Finds the entry whose place_id property holds this place_id, filtering as
+/// `url` when the schema says so and as `rich_text` otherwise.
+async fn find_existing_by_place_id(
+    client: &Client,
+    api_key: &str,
+    database_id: &str,
+    property: &str,
+    place_id: &str,
+) -> Result<Option<String>, String> {
+    let kind = match property_type(client, api_key, database_id, property).await.as_deref() {
+        Some("url") => "url",
+        _ => "rich_text",
+    };
+    find_existing_by_property(client, api_key, database_id, property, kind, place_id).await
+}
+
+async fn find_existing_by_property(
+    client: &Client,
+    api_key: &str,
+    database_id: &str,
+    property: &str,
+    kind: &str,
+    value: &str,
+) -> Result<Option<String>, String> {
+    let url = format!("{}/v1/databases/{}/query", crate::utils::api_base(Provider::Notion), database_id);
+    debug!("Querying Notion database {} on {} = {}", url, property, value);
+
+    let filter = json!({
+        "property": property,
+        kind: {
+            "equals": value
+        }
+    });
+    first_match(client, api_key, &url, filter).await
+}
 
-    if let Some(results) = response["results"].as_array() {
-        if let Some(first_result) = results.first() {
-            return Ok(first_result["id"].as_str().map(String::from));
+/// Runs a filtered query and returns the first matching page id, following
+/// `next_cursor` for at most `MAX_LOOKUP_PAGES` pages.
+async fn first_match(client: &Client, api_key: &str, url: &str, filter: Value) -> Result<Option<String>, String> {
+    let mut cursor: Option<String> = None;
+    for page in 1..=MAX_LOOKUP_PAGES {
+        let mut query = json!({ "filter": filter });
+        if let Some(cursor) = &cursor {
+            query["start_cursor"] = json!(cursor);
+        }
+
+        let response = client.post(url)
+            .provider_timeout("NOTION")
+            .header("Authorization", format!("Bearer {}", api_key))
+            .header("Notion-Version", "2022-06-28")
+            .json(&query)
+            .send_tracked("NOTION")
+            .await
+            .map_err(|e| e.to_string())?
+            .json::<Value>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        debug!("Notion query response: {:?}", response);
+
+        if let Some(id) = response["results"][0]["id"].as_str() {
+            debug!("Found a matching Notion entry after scanning {} page(s)", page);
+            return Ok(Some(id.to_string()));
+        }
+        match response["next_cursor"].as_str() {
+            Some(next) if response["has_more"].as_bool() == Some(true) => cursor = Some(next.to_string()),
+            _ => {
+                debug!("No matching Notion entry after scanning {} page(s)", page);
+                return Ok(None);
+            }
         }
     }
 
+    warn!("Stopped looking for a matching Notion entry after {} pages", MAX_LOOKUP_PAGES);
     Ok(None)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent_properties() -> Value {
+        json!({
+            "City": { "rich_text": [{ "text": { "content": "Paris" } }] },
+            "City Area": { "rich_text": [{ "text": { "content": "Marais" } }] },
+            "Website": { "url": "" },
+            "Price range": { "select": { "name": "€€" } },
+        })
+    }
+
+    #[test]
+    fn describes_a_body_path_validation_error() {
+        let body = r#"{"object":"error","status":400,"code":"validation_error","message":"body failed validation: body.properties.Website.url should be a string or `null`, instead was `\"\"`.","request_id":"a6ce9a3e-7b4c-4bb6-9d3f-7bd1b8a8d3c1"}"#;
+        assert_eq!(
+            describe_validation_error(body, &sent_properties()).as_deref(),
+            Some(r#"property "Website" (sent {"url":""}): body failed validation: body.properties.Website.url should be a string or `null`, instead was `""`."#)
+        );
+    }
+
+    #[test]
+    fn describes_a_type_mismatch() {
+        let body = r#"{"object":"error","status":400,"code":"validation_error","message":"Price range is expected to be select.","request_id":"0c8f1d0e-2f6b-4a5e-8f77-3f1f1e6c5b2a"}"#;
+        assert_eq!(
+            describe_validation_error(body, &sent_properties()).as_deref(),
+            Some(r#"property "Price range" (sent {"select":{"name":"€€"}}): Price range is expected to be select."#)
+        );
+    }
+
+    #[test]
+    fn longest_property_name_wins() {
+        let body = r#"{"object":"error","status":400,"code":"validation_error","message":"City Area is expected to be select."}"#;
+        assert!(describe_validation_error(body, &sent_properties()).unwrap().starts_with(r#"property "City Area""#));
+    }
+
+    #[test]
+    fn other_errors_are_not_described() {
+        let not_found = r#"{"object":"error","status":404,"code":"object_not_found","message":"Could not find database with ID: 8e2c.","request_id":"b1"}"#;
+        assert_eq!(describe_validation_error(not_found, &sent_properties()), None);
+
+        let unknown_property = r#"{"object":"error","status":400,"code":"validation_error","message":"Cuisine is not a property that exists.","request_id":"b2"}"#;
+        assert_eq!(describe_validation_error(unknown_property, &sent_properties()), None);
+
+        assert_eq!(describe_validation_error("<html>Bad Gateway</html>", &sent_properties()), None);
+    }
+
+    #[test]
+    fn synonyms_merge_into_one_option() {
+        let synonyms = parse_cuisine_synonyms("");
+        assert_eq!(multi_select_options("Pizza, Pizzerias, Italian", &synonyms), vec!["Pizza", "Italian"]);
+        assert_eq!(multi_select_options("sushi bars,Sushi,Japanese", &synonyms), vec!["Sushi", "Japanese"]);
+        assert_eq!(multi_select_options("Coffee & Tea, Coffee Shop", &synonyms), vec!["Coffee"]);
+    }
+
+    #[test]
+    fn canonical_cuisine_keeps_a_leading_emoji() {
+        let synonyms = parse_cuisine_synonyms("");
+        assert_eq!(canonical_cuisine("🍕 Pizzerias", &synonyms), "🍕 Pizza");
+        assert_eq!(canonical_cuisine("☕Coffee Shop", &synonyms), "☕Coffee");
+        assert_eq!(canonical_cuisine("🍜 Ramen", &synonyms), "🍜 Ramen");
+    }
+
+    #[test]
+    fn duplicates_ignore_the_emoji() {
+        let synonyms = parse_cuisine_synonyms("");
+        assert_eq!(multi_select_options("🍕 Pizza, Pizzerias, 🇮🇹 Italian, italian", &synonyms), vec!["🍕 Pizza", "🇮🇹 Italian"]);
+    }
+
+    #[test]
+    fn configured_synonyms_extend_and_override_the_defaults() {
+        let synonyms = parse_cuisine_synonyms("Ramen=noodles, Pizzerias=Neapolitan, broken, =Empty");
+        assert_eq!(synonyms.get("ramen").map(String::as_str), Some("Noodles"));
+        assert_eq!(synonyms.get("pizzerias").map(String::as_str), Some("Neapolitan"));
+        assert_eq!(synonyms.get("sushi bars").map(String::as_str), Some("Sushi"));
+        assert!(!synonyms.contains_key("broken") && !synonyms.contains_key(""));
+        assert_eq!(multi_select_options("Ramen, Noodles, Pizzerias", &synonyms), vec!["Noodles", "Neapolitan"]);
+    }
+
+
+    fn index_with(entries: IndexedEntries) -> EntryIndex {
+        EntryIndex { entries: Mutex::new(entries) }
+    }
+
+    #[test]
+    fn page_place_id_reads_text_and_url_properties() {
+        let text = json!({ "properties": { "Place ID": { "rich_text": [{ "plain_text": "ChIJ" }, { "plain_text": "abc" }] } } });
+        let url = json!({ "properties": { "Place ID": { "url": "ChIJxyz" } } });
+        let empty = json!({ "properties": { "Place ID": { "rich_text": [] } } });
+        assert_eq!(page_place_id(&text, "Place ID").as_deref(), Some("ChIJabc"));
+        assert_eq!(page_place_id(&url, "Place ID").as_deref(), Some("ChIJxyz"));
+        assert_eq!(page_place_id(&empty, "Place ID"), None);
+        assert_eq!(page_place_id(&text, "Other"), None);
+    }
+
+    #[test]
+    fn index_matches_renamed_entries_by_place_id() {
+        let mut entries = IndexedEntries::default();
+        entries.add("Old Name".to_string(), Some("page-1".to_string()), Some("ChIJabc".to_string()));
+        entries.add("Legacy".to_string(), Some("page-2".to_string()), None);
+        let index = index_with(entries);
+        assert!(matches!(index.lookup("New Name", Some("ChIJabc")), IndexLookup::Found(id) if id == "page-1"));
+        assert!(matches!(index.lookup("New Name", Some("ChIJother")), IndexLookup::Missing));
+        assert!(matches!(index.lookup("Old Name", None), IndexLookup::Found(id) if id == "page-1"));
+        assert!(matches!(index.lookup("Old Name", Some("ChIJother")), IndexLookup::Missing));
+        assert!(matches!(index.lookup("Legacy", Some("ChIJother")), IndexLookup::Found(id) if id == "page-2"));
+    }
+
+    #[test]
+    fn index_leaves_duplicate_titles_to_notion() {
+        let mut entries = IndexedEntries::default();
+        entries.add("Chez Nous".to_string(), Some("page-1".to_string()), None);
+        entries.add("Chez Nous".to_string(), Some("page-2".to_string()), None);
+        let index = index_with(entries);
+        assert!(matches!(index.lookup("Chez Nous", None), IndexLookup::Ambiguous));
+    }
+
+    #[test]
+    fn index_finds_pages_created_during_the_batch() {
+        let index = index_with(IndexedEntries::default());
+        index.insert("Chez Nous", Some("ChIJnew"), "page-2");
+        assert!(matches!(index.lookup("Chez Nous", None), IndexLookup::Found(id) if id == "page-2"));
+        assert!(matches!(index.lookup("Chez Nous Renamed", Some("ChIJnew")), IndexLookup::Found(id) if id == "page-2"));
+        assert!(matches!(index.lookup("chez nous ", None), IndexLookup::Ambiguous));
+    }
+}
@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use std::env;
+use crate::error::GastropathError;
+use crate::google_places::PlaceDetails;
+use crate::timing::StageTimings;
+
+/// A source of place details for a shared link or text query.
+#[async_trait(?Send)]
+pub trait PlaceProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn details(
+        &self,
+        client: &Client,
+        identifier: &str,
+        request_id: &str,
+        timings: &mut StageTimings,
+    ) -> Result<PlaceDetails, GastropathError>;
+}
+
+/// Google Places, the default (and so far only) provider.
+pub struct GooglePlaces;
+
+#[async_trait(?Send)]
+impl PlaceProvider for GooglePlaces {
+    fn name(&self) -> &'static str {
+        "google"
+    }
+
+    async fn details(
+        &self,
+        client: &Client,
+        identifier: &str,
+        request_id: &str,
+        timings: &mut StageTimings,
+    ) -> Result<PlaceDetails, GastropathError> {
+        crate::google_places::get_place_details(client, identifier, request_id, timings).await
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref PLACE_PROVIDER: Box<dyn PlaceProvider> = from_name(&configured_name()).unwrap_or_else(|| Box::new(GooglePlaces));
+}
+
+fn configured_name() -> String {
+    env::var("PLACE_PROVIDER").unwrap_or_else(|_| "google".to_string()).trim().to_lowercase()
+}
+
+fn from_name(name: &str) -> Option<Box<dyn PlaceProvider>> {
+    match name {
+        "google" => Some(Box::new(GooglePlaces)),
+        _ => None,
+    }
+}
+
+/// Checks at startup that `PLACE_PROVIDER`, if set, names a known provider.
+pub fn validate() -> Result<(), String> {
+    let name = configured_name();
+    from_name(&name)
+        .map(|_| ())
+        .ok_or_else(|| format!("Unknown PLACE_PROVIDER '{}', expected google", name))
+}
+
+/// The provider selected by `PLACE_PROVIDER` (default `google`).
+pub fn current() -> &'static dyn PlaceProvider {
+    PLACE_PROVIDER.as_ref()
+}
@@ -0,0 +1,66 @@
+use reqwest::Client;
+use serde_json::json;
+use std::env;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use log::{info, warn};
+use crate::RestaurantDetails;
+
+const DEFAULT_POST_PROCESS_TIMEOUT_SECS: u64 = 10;
+
+/// Hands a newly created entry to the configured hooks in the background: a POST to
+/// `POST_PROCESS_WEBHOOK_URL` and/or `POST_PROCESS_COMMAND` run by `sh -c` with the
+/// JSON on stdin. Each gets `POST_PROCESS_TIMEOUT_SECS` (default 10); failures are only logged.
+pub fn entry_created(client: &Client, details: &RestaurantDetails, page_id: &str) {
+    let webhook_url = env::var("POST_PROCESS_WEBHOOK_URL").ok().filter(|url| !url.is_empty());
+    let command = env::var("POST_PROCESS_COMMAND").ok().filter(|command| !command.is_empty());
+    if webhook_url.is_none() && command.is_none() {
+        return;
+    }
+
+    let timeout = Duration::from_secs(
+        env::var("POST_PROCESS_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_POST_PROCESS_TIMEOUT_SECS),
+    );
+    let payload = json!({ "page_id": page_id, "details": details });
+
+    if let Some(url) = webhook_url {
+        let client = client.clone();
+        let payload = payload.clone();
+        actix_web::rt::spawn(async move {
+            match client.post(&url).timeout(timeout).json(&payload).send().await {
+                Ok(response) if response.status().is_success() => info!("Post-process webhook accepted the entry"),
+                Ok(response) => warn!("Post-process webhook failed: HTTP {}", response.status()),
+                Err(e) => warn!("Post-process webhook failed: {}", e),
+            }
+        });
+    }
+
+    if let Some(command) = command {
+        actix_web::rt::spawn(async move {
+            match tokio::time::timeout(timeout, run_command(&command, payload.to_string())).await {
+                Ok(Ok(status)) if status.success() => info!("Post-process command succeeded"),
+                Ok(Ok(status)) => warn!("Post-process command failed: {}", status),
+                Ok(Err(e)) => warn!("Post-process command failed: {}", e),
+                Err(_) => warn!("Post-process command timed out after {}s and was killed", timeout.as_secs()),
+            }
+        });
+    }
+}
+
+async fn run_command(command: &str, input: String) -> std::io::Result<std::process::ExitStatus> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes()).await?;
+    }
+    child.wait().await
+}
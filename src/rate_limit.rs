@@ -0,0 +1,435 @@
+use actix_governor::{KeyExtractor, SimpleKeyExtractionError};
+use actix_web::dev::ServiceRequest;
+use reqwest::{RequestBuilder, Response};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use log::{info, warn};
+
+const DEFAULT_LOW_WATERMARK: u64 = 10;
+const DEFAULT_THROTTLE_MS: u64 = 1000;
+/// Notion documents an average of three requests per second per integration.
+const DEFAULT_NOTION_MIN_INTERVAL_MS: u64 = 334;
+const DEFAULT_QUEUE_DEPTH: usize = 10;
+const DEFAULT_QUEUE_MAX_WAIT_MS: u64 = 5000;
+
+lazy_static::lazy_static! {
+    static ref QUOTAS: Mutex<HashMap<&'static str, QuotaState>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Default)]
+struct QuotaState {
+    limit: Option<u64>,
+    remaining: Option<u64>,
+    reset_time: Option<String>,
+    blocked_until: Option<Instant>,
+    last_request: Option<Instant>,
+}
+
+pub trait TrackedSend {
+    /// Sends the request after any throttling the provider's recent quota headers call
+    /// for, then records the quota headers of the response.
+    fn send_tracked(self, provider: &'static str) -> impl Future<Output = reqwest::Result<Response>> + Send;
+}
+
+impl TrackedSend for RequestBuilder {
+    async fn send_tracked(self, provider: &'static str) -> reqwest::Result<Response> {
+        throttle(provider).await;
+        let response = self.send().await?;
+        record(provider, &response);
+        Ok(response)
+    }
+}
+
+async fn throttle(provider: &'static str) {
+    let low_watermark = env_u64("RATE_LIMIT_LOW_WATERMARK", DEFAULT_LOW_WATERMARK);
+    let throttle = Duration::from_millis(env_u64("RATE_LIMIT_THROTTLE_MS", DEFAULT_THROTTLE_MS));
+    let min_interval = match provider {
+        "NOTION" => Duration::from_millis(env_u64("NOTION_MIN_INTERVAL_MS", DEFAULT_NOTION_MIN_INTERVAL_MS)),
+        _ => Duration::ZERO,
+    };
+
+    let delay = {
+        let mut quotas = QUOTAS.lock().unwrap();
+        let state = quotas.entry(provider).or_default();
+        let now = Instant::now();
+
+        let mut delay = state.blocked_until.map_or(Duration::ZERO, |until| until.saturating_duration_since(now));
+        if let Some(last) = state.last_request {
+            delay = delay.max(min_interval.saturating_sub(now.duration_since(last)));
+        }
+        if state.remaining.is_some_and(|remaining| remaining <= low_watermark) {
+            delay = delay.max(throttle);
+        }
+
+        state.last_request = Some(now + delay);
+        delay
+    };
+
+    if !delay.is_zero() {
+        info!("Throttling {} request for {}ms", provider, delay.as_millis());
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn record(provider: &'static str, response: &Response) {
+    let header = |name: &str| response.headers().get(name).and_then(|v| v.to_str().ok());
+    let number = |name: &str| header(name).and_then(|v| v.trim().parse::<u64>().ok());
+
+    let mut quotas = QUOTAS.lock().unwrap();
+    let state = quotas.entry(provider).or_default();
+
+    if let Some(remaining) = number("RateLimit-Remaining") {
+        state.remaining = Some(remaining);
+    }
+    if let Some(limit) = number("RateLimit-DailyLimit").or_else(|| number("RateLimit-Limit")) {
+        state.limit = Some(limit);
+    }
+    if let Some(reset_time) = header("RateLimit-ResetTime").or_else(|| header("RateLimit-Reset")) {
+        state.reset_time = Some(reset_time.to_string());
+    }
+    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = Duration::from_secs(number("Retry-After").unwrap_or(1));
+        warn!("{} rate limit hit, pausing {} requests for {}s", provider, provider, retry_after.as_secs());
+        state.blocked_until = Some(Instant::now() + retry_after);
+    }
+}
+
+/// Last known quota per provider, for troubleshooting endpoints.
+pub fn snapshot() -> Value {
+    let quotas = QUOTAS.lock().unwrap();
+    quotas
+        .iter()
+        .map(|(provider, state)| {
+            let value = json!({
+                "limit": state.limit,
+                "remaining": state.remaining,
+                "reset_time": state.reset_time,
+            });
+            (provider.to_string(), value)
+        })
+        .collect::<serde_json::Map<String, Value>>()
+        .into()
+}
+
+/// Per-client token buckets used instead of Governor when `RATE_LIMIT_MODE=queue`: a
+/// client over the limit waits for its slot (up to `RATE_LIMIT_QUEUE_MAX_WAIT_MS`) rather
+/// than being rejected, and only gets a 429 once `RATE_LIMIT_QUEUE_DEPTH` of its requests
+/// are already waiting.
+pub struct RequestQueue {
+    clients: Mutex<HashMap<ClientKey, Bucket>>,
+    depth: usize,
+    max_wait: Duration,
+    replenish_interval: Duration,
+    burst: f64,
+}
+
+struct Bucket {
+    /// Negative when future tokens are already reserved by waiting requests.
+    tokens: f64,
+    updated: Instant,
+    /// Requests holding a reserved token until it comes up.
+    waiting: usize,
+}
+
+impl RequestQueue {
+    fn new(replenish_interval: Duration, burst: u32, depth: usize, max_wait: Duration) -> RequestQueue {
+        RequestQueue { clients: Mutex::new(HashMap::new()), depth, max_wait, replenish_interval, burst: burst as f64 }
+    }
+
+    /// Waits for a slot for `key`. Returns `false` when the request should be rejected with a 429.
+    pub async fn acquire(&self, key: &ClientKey) -> bool {
+        let Some(wait) = self.reserve(key, Instant::now()) else {
+            return false;
+        };
+        if !wait.is_zero() {
+            info!("Queueing request for {}ms", wait.as_millis());
+            tokio::time::sleep(wait).await;
+            if let Some(bucket) = self.clients.lock().unwrap().get_mut(key) {
+                bucket.waiting -= 1;
+            }
+        }
+        true
+    }
+
+    /// Takes a token from the client's bucket, returning how long to wait for it, or `None`
+    /// if the client already has `depth` requests waiting or the wait exceeds the max wait.
+    fn reserve(&self, key: &ClientKey, now: Instant) -> Option<Duration> {
+        let mut clients = self.clients.lock().unwrap();
+        // Forget clients whose bucket is full again and who have nothing waiting
+        clients.retain(|_, bucket| {
+            self.refill(bucket, now);
+            bucket.waiting > 0 || bucket.tokens < self.burst
+        });
+        let bucket = clients.entry(key.clone()).or_insert(Bucket { tokens: self.burst, updated: now, waiting: 0 });
+
+        let wait = if bucket.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            self.replenish_interval.mul_f64(1.0 - bucket.tokens)
+        };
+        if !wait.is_zero() && bucket.waiting >= self.depth {
+            warn!("Request queue is full, rejecting request");
+            return None;
+        }
+        if wait > self.max_wait {
+            warn!("Request would wait {}ms for a slot, rejecting", wait.as_millis());
+            return None;
+        }
+
+        bucket.tokens -= 1.0;
+        if !wait.is_zero() {
+            bucket.waiting += 1;
+        }
+        Some(wait)
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let refilled = now.saturating_duration_since(bucket.updated).as_secs_f64() / self.replenish_interval.as_secs_f64();
+        bucket.tokens = (bucket.tokens + refilled).min(self.burst);
+        bucket.updated = bucket.updated.max(now);
+    }
+}
+
+/// The queues standing in for the write and read Governor limits in `RATE_LIMIT_MODE=queue`,
+/// with the same rates and the same client keys.
+pub struct RequestQueues {
+    writes: RequestQueue,
+    reads: RequestQueue,
+    key_extractor: ClientKeyExtractor,
+}
+
+impl RequestQueues {
+    /// Takes the write and read limits as (replenish seconds, burst).
+    pub fn from_env(writes: (u64, u32), reads: (u64, u32), key_extractor: ClientKeyExtractor) -> Option<RequestQueues> {
+        if env::var("RATE_LIMIT_MODE").unwrap_or_default().to_lowercase() != "queue" {
+            return None;
+        }
+
+        let depth = env::var("RATE_LIMIT_QUEUE_DEPTH").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_QUEUE_DEPTH);
+        let max_wait = Duration::from_millis(env_u64("RATE_LIMIT_QUEUE_MAX_WAIT_MS", DEFAULT_QUEUE_MAX_WAIT_MS));
+        let queue = |(replenish_secs, burst): (u64, u32)| RequestQueue::new(Duration::from_secs(replenish_secs), burst, depth, max_wait);
+        info!("Rate limiting in queue mode (depth {} per client, max wait {}ms)", depth, max_wait.as_millis());
+        Some(RequestQueues { writes: queue(writes), reads: queue(reads), key_extractor })
+    }
+
+    /// Waits for the client's slot in the write or read queue. Returns `false` when the
+    /// request should be rejected with a 429; trusted clients are never queued.
+    pub async fn acquire(&self, req: &ServiceRequest, write: bool) -> bool {
+        let queue = if write { &self.writes } else { &self.reads };
+        match self.key_extractor.extract(req) {
+            Ok(ClientKey::Trusted) => true,
+            Ok(key) => queue.acquire(&key).await,
+            Err(_) => {
+                warn!("Could not extract the client IP address, rejecting request");
+                false
+            }
+        }
+    }
+}
+
+/// A network in CIDR notation; a bare address is a single-host network.
+#[derive(Debug, Clone, Copy)]
+struct Cidr {
+    network: IpAddr,
+    prefix: u32,
+}
+
+impl Cidr {
+    fn parse(text: &str) -> Result<Cidr, String> {
+        let invalid = || format!("Invalid RATE_LIMIT_TRUSTED_IPS entry '{}', expected an IP or CIDR", text);
+        let (address, prefix) = match text.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u32>().map_err(|_| invalid())?)),
+            None => (text, None),
+        };
+        let network: IpAddr = address.parse().map_err(|_| invalid())?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        match prefix.unwrap_or(max_prefix) {
+            prefix if prefix <= max_prefix => Ok(Cidr { network, prefix }),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        // Compare IPv4-mapped IPv6 peers (::ffff:a.b.c.d) as IPv4
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            ip => ip,
+        };
+        let (network, ip, bits) = match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => (u32::from(network) as u128, u32::from(ip) as u128, 32),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+        let mask = if self.prefix == 0 { 0 } else { u128::MAX << (bits - self.prefix) };
+        network & mask == ip & mask
+    }
+}
+
+/// Rate limiting key: the client IP, or `Trusted` for clients in `RATE_LIMIT_TRUSTED_IPS`,
+/// which Governor lets through unlimited.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClientKey {
+    Trusted,
+    Ip(IpAddr),
+}
+
+/// Keys requests by client IP: the peer address, or with `RATE_LIMIT_TRUST_FORWARDED_FOR=true`
+/// and a peer in `RATE_LIMIT_TRUSTED_PROXIES`, the right-most `X-Forwarded-For` entry that
+/// isn't one of those proxies. Entries further left are set by the client and never used.
+#[derive(Debug, Clone)]
+pub struct ClientKeyExtractor {
+    trusted: Arc<Vec<Cidr>>,
+    trust_forwarded_for: bool,
+    proxies: Arc<Vec<Cidr>>,
+}
+
+impl ClientKeyExtractor {
+    /// Reads `RATE_LIMIT_TRUSTED_IPS`, a comma-separated list of IPs and CIDRs (none by default),
+    /// and `RATE_LIMIT_TRUSTED_PROXIES` in the same format (default `127.0.0.1,::1`).
+    pub fn from_env() -> Result<ClientKeyExtractor, String> {
+        let trusted = parse_cidrs(&env::var("RATE_LIMIT_TRUSTED_IPS").unwrap_or_default())?;
+        let trust_forwarded_for = env::var("RATE_LIMIT_TRUST_FORWARDED_FOR").map(|v| v == "true" || v == "1").unwrap_or(false);
+        let proxies = parse_cidrs(&env::var("RATE_LIMIT_TRUSTED_PROXIES").unwrap_or_else(|_| "127.0.0.1,::1".to_string()))?;
+        if !trusted.is_empty() {
+            info!("Rate limits don't apply to {} trusted network(s)", trusted.len());
+        }
+        Ok(ClientKeyExtractor { trusted: Arc::new(trusted), trust_forwarded_for, proxies: Arc::new(proxies) })
+    }
+
+    fn client_ip(&self, req: &ServiceRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr().map(|addr| addr.ip())?;
+        if !self.trust_forwarded_for || !self.is_proxy(peer) {
+            return Some(peer);
+        }
+        let forwarded = req.headers().get("X-Forwarded-For").and_then(|value| value.to_str().ok());
+        Some(self.forwarded_client(peer, forwarded))
+    }
+
+    /// Walks `X-Forwarded-For` from the right, past the trusted proxies. An entry that
+    /// doesn't parse stops the walk at the last proxy, as it can't be vouched for.
+    fn forwarded_client(&self, peer: IpAddr, forwarded: Option<&str>) -> IpAddr {
+        let mut client = peer;
+        for entry in forwarded.unwrap_or_default().rsplit(',') {
+            if !self.is_proxy(client) {
+                break;
+            }
+            match entry.trim().parse() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+        }
+        client
+    }
+
+    fn is_proxy(&self, ip: IpAddr) -> bool {
+        self.proxies.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    fn is_trusted(&self, req: &ServiceRequest) -> bool {
+        self.client_ip(req).is_some_and(|ip| self.trusted.iter().any(|cidr| cidr.contains(ip)))
+    }
+}
+
+impl KeyExtractor for ClientKeyExtractor {
+    type Key = ClientKey;
+    type KeyExtractionError = SimpleKeyExtractionError<&'static str>;
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        if self.is_trusted(req) {
+            return Ok(ClientKey::Trusted);
+        }
+        self.client_ip(req)
+            .map(ClientKey::Ip)
+            .ok_or_else(|| SimpleKeyExtractionError::new("Could not extract the client IP address from the request"))
+    }
+
+    fn whitelisted_keys(&self) -> Vec<Self::Key> {
+        vec![ClientKey::Trusted]
+    }
+}
+
+fn parse_cidrs(list: &str) -> Result<Vec<Cidr>, String> {
+    list.split(',').map(str::trim).filter(|entry| !entry.is_empty()).map(Cidr::parse).collect()
+}
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extractor(proxies: &str) -> ClientKeyExtractor {
+        ClientKeyExtractor { trusted: Arc::new(Vec::new()), trust_forwarded_for: true, proxies: Arc::new(parse_cidrs(proxies).unwrap()) }
+    }
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_client_ignores_entries_set_by_the_client() {
+        let extractor = extractor("127.0.0.1");
+        assert_eq!(extractor.forwarded_client(ip("127.0.0.1"), Some("127.0.0.1, 203.0.113.7")), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn forwarded_client_skips_chained_proxies() {
+        let extractor = extractor("127.0.0.1,10.0.0.0/8");
+        assert_eq!(extractor.forwarded_client(ip("127.0.0.1"), Some("198.51.100.1, 203.0.113.7, 10.1.2.3")), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn forwarded_client_stops_at_an_unparsable_entry() {
+        let extractor = extractor("127.0.0.1");
+        assert_eq!(extractor.forwarded_client(ip("127.0.0.1"), Some("203.0.113.7, unknown")), ip("127.0.0.1"));
+        assert_eq!(extractor.forwarded_client(ip("127.0.0.1"), None), ip("127.0.0.1"));
+    }
+
+    fn queue(depth: usize, max_wait_ms: u64) -> RequestQueue {
+        RequestQueue::new(Duration::from_secs(1), 1, depth, Duration::from_millis(max_wait_ms))
+    }
+
+    #[test]
+    fn queue_rejects_a_client_with_too_many_requests_waiting() {
+        let queue = queue(2, 10_000);
+        let (client, other) = (ClientKey::Ip(ip("203.0.113.7")), ClientKey::Ip(ip("203.0.113.8")));
+        let now = Instant::now();
+        assert_eq!(queue.reserve(&client, now), Some(Duration::ZERO));
+        assert_eq!(queue.reserve(&client, now), Some(Duration::from_secs(1)));
+        assert_eq!(queue.reserve(&client, now), Some(Duration::from_secs(2)));
+        assert_eq!(queue.reserve(&client, now), None);
+        // Other clients have their own bucket and queue
+        assert_eq!(queue.reserve(&other, now), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn queue_rejects_waits_over_the_max_wait() {
+        let queue = queue(10, 1500);
+        let client = ClientKey::Ip(ip("203.0.113.7"));
+        let now = Instant::now();
+        assert_eq!(queue.reserve(&client, now), Some(Duration::ZERO));
+        assert_eq!(queue.reserve(&client, now), Some(Duration::from_secs(1)));
+        assert_eq!(queue.reserve(&client, now), None);
+        // The bucket refills over time
+        assert_eq!(queue.reserve(&client, now + Duration::from_secs(3)), Some(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn acquire_waits_for_a_slot_and_rejects_past_the_depth() {
+        let queue = RequestQueue::new(Duration::from_millis(50), 1, 1, Duration::from_secs(1));
+        let client = ClientKey::Ip(ip("203.0.113.7"));
+        assert!(queue.acquire(&client).await);
+        let started = Instant::now();
+        let (first, second) = tokio::join!(queue.acquire(&client), queue.acquire(&client));
+        assert!(first && !second);
+        assert!(started.elapsed() >= Duration::from_millis(40), "took {:?}", started.elapsed());
+        assert_eq!(queue.clients.lock().unwrap()[&client].waiting, 0);
+    }
+}
@@ -0,0 +1,89 @@
+use reqwest::Client;
+use serde_json::Value;
+use std::fs;
+use log::{info, error, warn};
+
+/// Imports every place of a Google Takeout "Saved Places.json" export, one at a time.
+pub async fn import(client: &Client, path: &str) {
+    info!("Importing Google Takeout saved places from: {}", path);
+
+    let queries = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| parse_saved_places(&contents))
+    {
+        Ok(queries) => queries,
+        Err(e) => {
+            error!("Failed to read Takeout file {}: {}", path, e);
+            return;
+        }
+    };
+
+    let entry_index = crate::notion::EntryIndex::load_if_enabled(client).await;
+
+    let total = queries.len();
+    let mut succeeded = 0;
+    for (index, query) in queries.iter().enumerate() {
+        if let Some(entry_index) = &entry_index {
+            entry_index.refresh_if_stale(client).await;
+        }
+
+        let request_id = chrono::Utc::now().format("%Y%m%d%H%M%S%f").to_string();
+        let result = crate::logging::REQUEST_ID
+            .scope(request_id.clone(), crate::add_place(client, query, None, &request_id, entry_index.as_ref()))
+            .await;
+
+        match result {
+            Ok(message) => {
+                succeeded += 1;
+                info!("[{}/{}] {}: {}", index + 1, total, query, message);
+            }
+            Err(e) => warn!("[{}/{}] {}: {}", index + 1, total, query, e),
+        }
+    }
+
+    info!("Takeout import finished: {} of {} places imported", succeeded, total);
+}
+
+/// Extracts a text query ("name, address") for every feature of the Takeout GeoJSON.
+/// Handles both the current (`location.name`) and legacy (`Location."Business Name"`)
+/// property layouts. Entries without a usable name are skipped with a warning.
+fn parse_saved_places(contents: &str) -> Result<Vec<String>, String> {
+    let json: Value = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    let features = json["features"]
+        .as_array()
+        .ok_or("Not a Takeout saved-places file: missing 'features'")?;
+
+    let mut queries = Vec::new();
+    for (index, feature) in features.iter().enumerate() {
+        match place_query(feature) {
+            Some(query) => queries.push(query),
+            None => warn!("Skipping malformed Takeout entry #{}: {}", index + 1, feature),
+        }
+    }
+
+    info!("Found {} importable places out of {} entries", queries.len(), features.len());
+    Ok(queries)
+}
+
+fn place_query(feature: &Value) -> Option<String> {
+    let properties = &feature["properties"];
+    let location = if properties["location"].is_object() {
+        &properties["location"]
+    } else {
+        &properties["Location"]
+    };
+
+    let name = non_empty(&location["name"])
+        .or_else(|| non_empty(&location["Business Name"]))
+        .or_else(|| non_empty(&properties["Title"]))?;
+    let address = non_empty(&location["address"]).or_else(|| non_empty(&location["Address"]));
+
+    Some(match address {
+        Some(address) => format!("{}, {}", name, address),
+        None => name.to_string(),
+    })
+}
+
+fn non_empty(value: &Value) -> Option<&str> {
+    value.as_str().map(str::trim).filter(|s| !s.is_empty())
+}
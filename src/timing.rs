@@ -0,0 +1,45 @@
+use serde_json::{json, Value};
+use std::time::Instant;
+use log::info;
+
+/// Durations of the pipeline stages of one request, in the order they ran.
+#[derive(Debug)]
+pub struct StageTimings {
+    started: Instant,
+    started_at: chrono::DateTime<chrono::Utc>,
+    stages: Vec<(&'static str, u128)>,
+}
+
+impl StageTimings {
+    pub fn start() -> Self {
+        StageTimings { started: Instant::now(), started_at: chrono::Utc::now(), stages: Vec::new() }
+    }
+
+    /// Records a stage that began at `stage_started` and ended now.
+    pub fn record(&mut self, stage: &'static str, stage_started: Instant) {
+        self.stages.push((stage, stage_started.elapsed().as_millis()));
+    }
+
+    pub fn total_ms(&self) -> u128 {
+        self.started.elapsed().as_millis()
+    }
+
+    /// Logs a single `stage=ms` summary line for the request.
+    pub fn log(&self, request_id: &str) {
+        let stages: Vec<String> = self.stages
+            .iter()
+            .map(|(stage, ms)| format!("{}={}ms", stage, ms))
+            .collect();
+        info!("Request {} timings: {} total={}ms", request_id, stages.join(" "), self.total_ms());
+    }
+
+    /// The timings as reported in API responses with `?timing=true`.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "started_at": self.started_at.to_rfc3339(),
+            "finished_at": chrono::Utc::now().to_rfc3339(),
+            "total_ms": self.total_ms() as u64,
+            "stages": self.stages.iter().map(|(stage, ms)| json!({ "stage": stage, "ms": *ms as u64 })).collect::<Vec<_>>(),
+        })
+    }
+}
@@ -1,5 +1,11 @@
 use url::Url;
-use log::{info, debug, error};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::RequestBuilder;
+use sha1::{Digest, Sha1};
+use std::env;
+use std::time::Duration;
+use log::{info, debug, error, warn};
+use crate::error::GastropathError;
 
 pub fn validate_and_sanitize_url(url: &str) -> Result<String, String> {
     debug!("Validating and sanitizing URL: {}", url);
@@ -14,17 +20,50 @@ pub fn validate_and_sanitize_url(url: &str) -> Result<String, String> {
         "Invalid URL format".to_string()
     })?;
 
-    if parsed_url.host_str() != Some("maps.app.goo.gl") {
-        error!("URL is not from a trusted domain");
-        return Err("URL is not from a trusted domain".to_string());
-    }
-
-    if !parsed_url.path().starts_with('/') || parsed_url.path().len() < 2 {
-        error!("Invalid URL path");
-        return Err("Invalid URL path".to_string());
-    }
+    let allowed_params: &[&str] = match parsed_url.host_str() {
+        Some("maps.app.goo.gl") => {
+            if !parsed_url.path().starts_with('/') || parsed_url.path().len() < 2 {
+                error!("Invalid URL path");
+                return Err("Invalid URL path".to_string());
+            }
+            &["g_st"]
+        }
+        Some("goo.gl") => {
+            if !parsed_url.path().starts_with("/maps/") || parsed_url.path().len() <= "/maps/".len() {
+                error!("Invalid URL path");
+                return Err("Invalid URL path".to_string());
+            }
+            &[]
+        }
+        Some(host) if is_full_maps_host(host) => {
+            if !parsed_url.path().starts_with("/maps") {
+                error!("Google URL is not a Maps link");
+                return Err("Google URL is not a Maps link".to_string());
+            }
+            // Everything a place can be identified by; the `data=` element is part of the path
+            &["q", "query", "query_place_id", "api", "ftid", "cid", "ludocid", "lrd"]
+        }
+        Some("maps.apple.com") => {
+            if !parsed_url.query_pairs().any(|(key, _)| key == "q" || key == "name") {
+                error!("Apple Maps URL has no place name");
+                return Err("Apple Maps URL has no place name".to_string());
+            }
+            &["q", "name", "address", "ll", "sll", "coordinate"]
+        }
+        Some(host) if extra_shortener_hosts().iter().any(|h| h == host) => {
+            if parsed_url.path().len() < 2 {
+                error!("Invalid URL path");
+                return Err("Invalid URL path".to_string());
+            }
+            // Where it leads is only known after expansion, which requires a Google Maps link
+            &[]
+        }
+        _ => {
+            error!("URL is not from a trusted domain");
+            return Err("URL is not from a trusted domain".to_string());
+        }
+    };
 
-    let allowed_params = ["g_st"];
     let sanitized_query: Vec<(String, String)> = parsed_url
         .query_pairs()
         .filter(|(key, _)| allowed_params.contains(&key.as_ref()))
@@ -43,6 +82,56 @@ pub fn validate_and_sanitize_url(url: &str) -> Result<String, String> {
     Ok(sanitized_url)
 }
 
+/// Checks a bare Google customer id (CID) is a decimal number, returning it trimmed.
+pub fn validate_cid(cid: &str) -> Result<String, String> {
+    let cid = cid.trim();
+    if cid.is_empty() || cid.parse::<u64>().is_err() {
+        error!("Invalid CID: {}", cid);
+        return Err("CID must be a decimal number".to_string());
+    }
+    Ok(cid.to_string())
+}
+
+/// Finds a personal note attached to a shared URL, e.g. `...#note=great+ramen` or `...?note=...`.
+/// The recognized key is `NOTE_URL_KEY` (default `note`). Sanitizing the URL drops it afterwards.
+pub fn extract_note(url: &str) -> Option<String> {
+    let key = env::var("NOTE_URL_KEY").unwrap_or_else(|_| "note".to_string());
+    let parsed_url = Url::parse(url).ok()?;
+
+    let from_fragment = parsed_url
+        .fragment()
+        .and_then(|fragment| url::form_urlencoded::parse(fragment.as_bytes()).find(|(k, _)| *k == key).map(|(_, v)| v.into_owned()));
+    let note = from_fragment.or_else(|| parsed_url.query_pairs().find(|(k, _)| *k == key).map(|(_, v)| v.into_owned()))?;
+
+    let note = note.trim();
+    (!note.is_empty()).then(|| note.to_string())
+}
+
+const DEFAULT_MIN_NAME_LENGTH: usize = 2;
+
+/// Describes what looks wrong with a resolved place name, if anything: shorter than
+/// `MIN_NAME_LENGTH` characters (default 2), no letters at all, or just the street
+/// part of the address. The checks are lenient so real names pass.
+pub fn name_quality_issue(name: &str, address: &str) -> Option<String> {
+    let name = name.trim();
+    let min_length = env::var("MIN_NAME_LENGTH")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MIN_NAME_LENGTH);
+
+    if name.chars().count() < min_length {
+        return Some(format!("shorter than {} characters", min_length));
+    }
+    if !name.chars().any(char::is_alphabetic) {
+        return Some("contains no letters".to_string());
+    }
+    let street = address.split(',').next().unwrap_or_default().trim();
+    if !street.is_empty() && name.eq_ignore_ascii_case(street) && name.chars().any(|c| c.is_ascii_digit()) {
+        return Some("is the street address rather than a place name".to_string());
+    }
+    None
+}
+
 pub fn mask_api_key(key: &str) -> String {
     if key.len() > 5 {
         format!("{}{}",
@@ -54,12 +143,469 @@ pub fn mask_api_key(key: &str) -> String {
     }
 }
 
-pub async fn expand_short_url(short_url: &str) -> Result<String, Box<dyn std::error::Error>> {
+/// Sørensen–Dice coefficient over the character bigrams of both names,
+/// ignoring case, whitespace and punctuation. Returns a score in `0.0..=1.0`.
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    fn bigrams(s: &str) -> Vec<(char, char)> {
+        let chars: Vec<char> = s.to_lowercase().chars().filter(|c| c.is_alphanumeric()).collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    }
+
+    let a_bigrams = bigrams(a);
+    let mut b_bigrams = bigrams(b);
+    if a_bigrams.is_empty() || b_bigrams.is_empty() {
+        return if a.trim().eq_ignore_ascii_case(b.trim()) { 1.0 } else { 0.0 };
+    }
+
+    let total = a_bigrams.len() + b_bigrams.len();
+    let mut matches = 0;
+    for bigram in &a_bigrams {
+        if let Some(pos) = b_bigrams.iter().position(|b| b == bigram) {
+            b_bigrams.swap_remove(pos);
+            matches += 1;
+        }
+    }
+
+    2.0 * matches as f64 / total as f64
+}
+
+/// Derives a stable, shareable identifier for a restaurant:
+/// `<kebab-case name>-<first 8 hex chars of sha1(key)>`, where `key` is
+/// `place_id:<place_id>` or, without a place_id, `name:<lowercase name>|city:<lowercase city>`.
+pub fn restaurant_slug(place_id: Option<&str>, name: &str, city: &str) -> String {
+    let key = match place_id {
+        Some(place_id) => format!("place_id:{}", place_id),
+        None => format!("name:{}|city:{}", name.trim().to_lowercase(), city.trim().to_lowercase()),
+    };
+    let hash = format!("{:x}", Sha1::digest(key.as_bytes()));
+
+    let kebab_name = name
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if kebab_name.is_empty() {
+        hash[..8].to_string()
+    } else {
+        format!("{}-{}", kebab_name, &hash[..8])
+    }
+}
+
+const DEFAULT_HTTP_TIMEOUT_SECS: u64 = 15;
+const HTTP_CONNECT_TIMEOUT_SECS: u64 = 5;
+
+/// The total request timeout from `HTTP_TIMEOUT_SECS` (default 15), used unless a provider
+/// timeout overrides it.
+fn http_timeout() -> Duration {
+    Duration::from_secs(
+        env::var("HTTP_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_HTTP_TIMEOUT_SECS),
+    )
+}
+
+/// Settings every outbound client shares: the extra headers, the total timeout, at
+/// most 5 seconds to connect, and redirects checked against internal targets (which
+/// short link expansion relies on).
+pub fn http_client_builder() -> reqwest::ClientBuilder {
+    http_client_builder_with_timeout(http_timeout())
+}
+
+/// [`http_client_builder`] with an explicit total timeout instead of `HTTP_TIMEOUT_SECS`.
+pub fn http_client_builder_with_timeout(timeout: Duration) -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .default_headers(extra_headers())
+        .timeout(timeout)
+        .connect_timeout(timeout.min(Duration::from_secs(HTTP_CONNECT_TIMEOUT_SECS)))
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match check_redirect_target(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        }))
+}
+
+/// Parses `HTTP_EXTRA_HEADERS` (`Name: value` pairs separated by `;` or newlines)
+/// into headers sent with every outbound request, e.g. `Proxy-Authorization`.
+pub fn extra_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let raw = env::var("HTTP_EXTRA_HEADERS").unwrap_or_default();
+
+    for entry in raw.split([';', '\n']).map(str::trim).filter(|e| !e.is_empty()) {
+        let parsed = entry.split_once(':').and_then(|(name, value)| {
+            let name = HeaderName::from_bytes(name.trim().as_bytes()).ok()?;
+            let mut value = HeaderValue::from_str(value.trim()).ok()?;
+            value.set_sensitive(true);
+            Some((name, value))
+        });
+
+        match parsed {
+            Some((name, value)) => {
+                info!("Adding outbound header: {}", name);
+                headers.insert(name, value);
+            }
+            None => warn!("Ignoring malformed entry in HTTP_EXTRA_HEADERS"),
+        }
+    }
+
+    headers
+}
+
+/// An upstream API, named as in its environment variables (`GOOGLE_TIMEOUT`, ...).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Provider {
+    Google,
+    Yelp,
+    Foursquare,
+    Notion,
+    Cloudinary,
+}
+
+impl Provider {
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::Google => "GOOGLE",
+            Provider::Yelp => "YELP",
+            Provider::Foursquare => "FOURSQUARE",
+            Provider::Notion => "NOTION",
+            Provider::Cloudinary => "CLOUDINARY",
+        }
+    }
+
+    fn default_api_base(self) -> &'static str {
+        match self {
+            Provider::Google => "https://maps.googleapis.com",
+            Provider::Yelp => "https://api.yelp.com",
+            Provider::Foursquare => "https://api.foursquare.com",
+            Provider::Notion => "https://api.notion.com",
+            Provider::Cloudinary => "https://api.cloudinary.com",
+        }
+    }
+}
+
+pub const PROVIDERS: [Provider; 5] = [Provider::Google, Provider::Yelp, Provider::Foursquare, Provider::Notion, Provider::Cloudinary];
+
+/// Reads the `<PROVIDER>_TIMEOUT` override (in seconds) for one upstream provider.
+pub fn provider_timeout(provider: &str) -> Option<Duration> {
+    env::var(format!("{}_TIMEOUT", provider))
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+pub fn log_provider_timeouts() {
+    for provider in PROVIDERS.map(Provider::name) {
+        match provider_timeout(provider) {
+            Some(timeout) => info!("{} timeout: {}s", provider, timeout.as_secs()),
+            None => info!("{} timeout: {}s (HTTP_TIMEOUT_SECS)", provider, http_timeout().as_secs()),
+        }
+    }
+}
+
+/// Base URL of a provider's API, overridable with `<PROVIDER>_API_BASE` (e.g. to go
+/// through a reverse proxy).
+pub fn api_base(provider: Provider) -> String {
+    resolve_api_base(provider, env::var(format!("{}_API_BASE", provider.name())).ok())
+}
+
+fn resolve_api_base(provider: Provider, configured: Option<String>) -> String {
+    match configured.filter(|v| !v.trim().is_empty()) {
+        Some(base) => base.trim().trim_end_matches('/').to_string(),
+        None => provider.default_api_base().to_string(),
+    }
+}
+
+pub trait RequestBuilderExt {
+    /// Applies the provider's timeout override, leaving the client default otherwise.
+    fn provider_timeout(self, provider: &str) -> Self;
+}
+
+impl RequestBuilderExt for RequestBuilder {
+    fn provider_timeout(self, provider: &str) -> Self {
+        match provider_timeout(provider) {
+            Some(timeout) => self.timeout(timeout),
+            None => self,
+        }
+    }
+}
+
+const DEFAULT_EXPAND_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_EXPAND_RETRIES: u32 = 2;
+const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0 Safari/537.36";
+
+const MAX_REDIRECTS: usize = 10;
+
+/// Additional URL shorteners accepted in front of a Google Maps link, from
+/// `EXTRA_SHORTENER_HOSTS` (comma-separated, e.g. `bit.ly,t.co`). Empty by default.
+pub fn extra_shortener_hosts() -> Vec<String> {
+    env::var("EXTRA_SHORTENER_HOSTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|host| host.trim().to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+/// Hosts of full-length Google Maps links, which need no expansion.
+pub fn is_full_maps_host(host: &str) -> bool {
+    matches!(host, "google.com" | "www.google.com" | "maps.google.com")
+}
+
+fn is_google_maps_host(host: &str) -> bool {
+    host == "maps.app.goo.gl" || host == "goo.gl" || host == "google.com" || host.ends_with(".google.com")
+}
+
+/// Refuses redirect hops that could reach internal services: anything but HTTP(S),
+/// `localhost`, and loopback/private/link-local IP addresses.
+fn check_redirect_target(url: &Url) -> Result<(), String> {
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return Err(format!("Refusing to follow redirect to a {} URL", url.scheme()));
+    }
+    let blocked = match url.host() {
+        Some(url::Host::Domain(domain)) => domain == "localhost" || domain.ends_with(".localhost"),
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback() || ip.is_unspecified() || (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80,
+        None => true,
+    };
+    if blocked {
+        return Err(format!("Refusing to follow redirect to {}", url));
+    }
+    Ok(())
+}
+
+/// The Google Maps URL a consent page would continue to, from its `continue` parameter.
+fn consent_continue_url(url: &Url) -> Option<String> {
+    let target = url.query_pairs().find(|(key, _)| key == "continue")?.1.into_owned();
+    let parsed = Url::parse(&target).ok()?;
+    (parsed.scheme() == "https" && parsed.host_str().is_some_and(is_google_maps_host) && parsed.host_str() != Some("consent.google.com"))
+        .then_some(target)
+}
+
+/// Requests the consent page's target again with a `CONSENT` cookie, which reqwest
+/// strips on the cross-host redirect from the short link. Returns the URL it ends on,
+/// or `None` if Google still asks for consent or the request fails.
+async fn follow_with_consent_cookie(client: &reqwest::Client, url: &str, timeout: Duration) -> Option<String> {
+    let response = client
+        .get(url)
+        .header(reqwest::header::USER_AGENT, BROWSER_USER_AGENT)
+        .header(reqwest::header::ACCEPT_LANGUAGE, "en-US,en;q=0.9")
+        .header(reqwest::header::COOKIE, "CONSENT=YES+")
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(|e| debug!("Retrying {} with a consent cookie failed: {}", url, e))
+        .ok()?;
+    let landed = response.url();
+    (response.status().is_success() && landed.host_str() != Some("consent.google.com") && landed.host_str().is_some_and(is_google_maps_host))
+        .then(|| landed.to_string())
+}
+
+/// Follows a short link's redirects with browser-like headers, retrying up to
+/// `EXPAND_RETRIES` times, each attempt bounded by `EXPAND_TIMEOUT` seconds. Every hop
+/// is checked against internal targets (by the shared client's redirect policy, see
+/// [`http_client_builder`]) and the final URL must be on a Google host. A link that can't
+/// be followed fails with [`GastropathError::Expansion`], as opposed to the place behind
+/// it not being found.
+pub async fn expand_short_url(client: &reqwest::Client, short_url: &str) -> Result<String, GastropathError> {
     debug!("Expanding short URL: {}", short_url);
-    let client = reqwest::Client::new();
-    let response = client.get(short_url).send().await?;
-    let expanded_url = response.url().to_string();
-    info!("Expanded URL: {}", expanded_url);
-    Ok(expanded_url)
+    let timeout = env::var("EXPAND_TIMEOUT")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_EXPAND_TIMEOUT_SECS);
+    let retries = env::var("EXPAND_RETRIES")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_EXPAND_RETRIES);
+
+    let consent_bypass = env::var("EXPAND_CONSENT_BYPASS").map(|v| v != "false" && v != "0").unwrap_or(true);
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = client
+            .get(short_url)
+            .header(reqwest::header::USER_AGENT, BROWSER_USER_AGENT)
+            .header(reqwest::header::ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")
+            .header(reqwest::header::ACCEPT_LANGUAGE, "en-US,en;q=0.9")
+            .timeout(Duration::from_secs(timeout))
+            .send()
+            .await;
+
+        let reason = match result {
+            Ok(response) if response.url().host_str() == Some("consent.google.com") => {
+                match consent_continue_url(response.url()) {
+                    Some(target) if consent_bypass => {
+                        match follow_with_consent_cookie(client, &target, Duration::from_secs(timeout)).await {
+                            Some(expanded_url) => {
+                                info!("Expanding {} hit Google's consent page, retried with a consent cookie: {}", short_url, expanded_url);
+                                return Ok(expanded_url);
+                            }
+                            None => {
+                                info!("Expanding {} hit Google's consent page, the consent cookie was refused, using its target: {}", short_url, target);
+                                return Ok(target);
+                            }
+                        }
+                    }
+                    _ => {
+                        warn!("Expanding {} stopped at Google's consent page", short_url);
+                        "stopped at Google's consent page".to_string()
+                    }
+                }
+            }
+            Ok(response) if response.status().is_success() || response.status().is_redirection() => {
+                let expanded_url = response.url().to_string();
+                info!("Expanded URL: {}", expanded_url);
+                // Other shorteners may point anywhere, only Google Maps links are processed
+                if !response.url().host_str().is_some_and(is_google_maps_host) {
+                    error!("Short URL {} does not lead to Google Maps: {}", short_url, expanded_url);
+                    return Err(GastropathError::Validation("URL does not lead to a Google Maps link".to_string()));
+                }
+                return Ok(expanded_url);
+            }
+            Ok(response) => format!("HTTP {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+
+        if attempts > retries {
+            return Err(GastropathError::Expansion(format!(
+                "Failed to expand short URL: Could not expand {} after {} attempt(s): {}",
+                short_url, attempts, reason
+            )));
+        }
+        warn!("Expanding {} failed (attempt {}): {}, retrying", short_url, attempts, reason);
+        tokio::time::sleep(Duration::from_millis(500 * attempts as u64)).await;
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apple_links_keep_only_place_params() {
+        assert_eq!(
+            validate_and_sanitize_url("https://maps.apple.com/?q=Chez%20Janou&ll=48.8571,2.3654&z=16&t=m&auid=123").as_deref(),
+            Ok("https://maps.apple.com/?q=Chez+Janou&ll=48.8571%2C2.3654")
+        );
+        assert_eq!(
+            validate_and_sanitize_url("https://maps.apple.com/?name=Chez%20Janou&address=Paris&coordinate=48.8571,2.3654").as_deref(),
+            Ok("https://maps.apple.com/?name=Chez+Janou&address=Paris&coordinate=48.8571%2C2.3654")
+        );
+    }
+
+    #[test]
+    fn apple_links_without_a_name_are_rejected() {
+        assert!(validate_and_sanitize_url("https://maps.apple.com/?ll=48.8571,2.3654").is_err());
+        assert!(validate_and_sanitize_url("https://apple.com/maps/?q=Chez%20Janou").is_err());
+    }
+
+    #[test]
+    fn single_character_names_are_too_short() {
+        assert_eq!(name_quality_issue("A", "1 Main St, Springfield").as_deref(), Some("shorter than 2 characters"));
+        assert_eq!(name_quality_issue("  ", "1 Main St, Springfield").as_deref(), Some("shorter than 2 characters"));
+    }
+
+    #[test]
+    fn digit_only_names_have_no_letters() {
+        assert_eq!(name_quality_issue("1234", "1234 Main St, Springfield").as_deref(), Some("contains no letters"));
+        assert_eq!(name_quality_issue("12-34", "").as_deref(), Some("contains no letters"));
+    }
+
+    #[test]
+    fn names_equal_to_the_street_address_are_flagged() {
+        assert_eq!(
+            name_quality_issue("12 rue de Rivoli", "12 Rue de Rivoli, 75004 Paris, France").as_deref(),
+            Some("is the street address rather than a place name")
+        );
+    }
+
+    #[test]
+    fn real_names_pass() {
+        assert_eq!(name_quality_issue("寿司", "東京都中央区銀座4-2-15"), None);
+        assert_eq!(name_quality_issue("Café Légère", "5 Rue Oberkampf, 75011 Paris"), None);
+        assert_eq!(name_quality_issue("Ça Va", "12 Rue de Rivoli, 75004 Paris"), None);
+        assert_eq!(name_quality_issue("21 Club", "21 W 52nd St, New York"), None);
+        // A street name that is also the place name, without a number, is a real name
+        assert_eq!(name_quality_issue("Broadway", "Broadway, New York"), None);
+    }
+
+    #[test]
+    fn every_google_host_shape_is_accepted_and_sanitized() {
+        let cases = [
+            ("https://maps.app.goo.gl/AbCdEf123?g_st=ic&utm_source=share", "https://maps.app.goo.gl/AbCdEf123?g_st=ic"),
+            ("https://goo.gl/maps/AbCdEf123?utm_source=share", "https://goo.gl/maps/AbCdEf123"),
+            (
+                "https://google.com/maps/place/Le+Comptoir/data=!4m2!3m1!1s0x47e66e2964e34e2d:0x8ddca9ee380ef7e0?hl=fr&entry=ttu",
+                "https://google.com/maps/place/Le+Comptoir/data=!4m2!3m1!1s0x47e66e2964e34e2d:0x8ddca9ee380ef7e0",
+            ),
+            (
+                "https://www.google.com/maps/place/Le+Comptoir/@48.852,2.3388,17z?authuser=0&cid=10222232094831998944",
+                "https://www.google.com/maps/place/Le+Comptoir/@48.852,2.3388,17z?cid=10222232094831998944",
+            ),
+            ("https://maps.google.com/maps?q=Le+Comptoir&ftid=0x1:0x2&hl=en", "https://maps.google.com/maps?q=Le+Comptoir&ftid=0x1%3A0x2"),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(validate_and_sanitize_url(url).as_deref(), Ok(expected), "{}", url);
+        }
+    }
+
+    #[test]
+    fn google_hosts_only_accept_maps_paths() {
+        assert!(validate_and_sanitize_url("https://www.google.com/search?q=Le+Comptoir").is_err());
+        assert!(validate_and_sanitize_url("https://goo.gl/AbCdEf123").is_err());
+        assert!(validate_and_sanitize_url("https://goo.gl/maps/").is_err());
+        assert!(validate_and_sanitize_url("https://maps.app.goo.gl/").is_err());
+        assert!(validate_and_sanitize_url("https://evil.google.com.example/maps/place/x").is_err());
+    }
+
+    #[tokio::test]
+    async fn client_gives_up_on_a_server_that_never_answers() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        // Accepts the connection, then never writes a response
+        let server = tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        });
+
+        let client = http_client_builder_with_timeout(Duration::from_secs(1)).build().unwrap();
+        let started = std::time::Instant::now();
+        let result = client.get(format!("http://{}/", address)).send().await;
+
+        assert!(result.unwrap_err().is_timeout());
+        assert!(started.elapsed() < Duration::from_secs(3), "took {:?}", started.elapsed());
+        server.abort();
+    }
+
+
+    #[test]
+    fn api_base_defaults_to_the_public_host_and_trims_overrides() {
+        assert_eq!(resolve_api_base(Provider::Yelp, None), "https://api.yelp.com");
+        assert_eq!(resolve_api_base(Provider::Yelp, Some(" ".to_string())), "https://api.yelp.com");
+        assert_eq!(
+            resolve_api_base(Provider::Cloudinary, Some(" https://gateway.example.com/cloudinary/ ".to_string())),
+            "https://gateway.example.com/cloudinary"
+        );
+    }
+
+    #[test]
+    fn consent_continue_url_only_accepts_google_maps_targets() {
+        let consent = |target: &str| Url::parse_with_params("https://consent.google.com/ml?hl=en", &[("continue", target)]).unwrap();
+        assert_eq!(
+            consent_continue_url(&consent("https://www.google.com/maps/place/Le+Comptoir?entry=tts")).as_deref(),
+            Some("https://www.google.com/maps/place/Le+Comptoir?entry=tts")
+        );
+        assert_eq!(consent_continue_url(&consent("http://www.google.com/maps/place/Le+Comptoir")), None);
+        assert_eq!(consent_continue_url(&consent("https://evil.example/maps")), None);
+        assert_eq!(consent_continue_url(&consent("https://consent.google.com/ml")), None);
+        assert_eq!(consent_continue_url(&consent("not a url")), None);
+        assert_eq!(consent_continue_url(&Url::parse("https://consent.google.com/ml?hl=en").unwrap()), None);
+    }
+}
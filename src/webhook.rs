@@ -0,0 +1,81 @@
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use log::{info, warn, error};
+
+const DEFAULT_WEBHOOK_MAX_RETRIES: u32 = 3;
+const DEFAULT_DEAD_LETTER_PATH: &str = "logs/webhook_dead_letter.jsonl";
+
+/// Posts a notification to `NOTIFY_WEBHOOK_URL` in the background. The payload carries
+/// the message as both `content` (Discord) and `text` (Slack). Does nothing when unset.
+pub fn notify(client: &Client, message: String) {
+    let url = match env::var("NOTIFY_WEBHOOK_URL") {
+        Ok(url) if !url.is_empty() => url,
+        _ => return,
+    };
+    let payload = json!({ "content": message, "text": message });
+    let client = client.clone();
+    actix_web::rt::spawn(async move {
+        deliver(&client, &url, &payload).await;
+    });
+}
+
+/// Tries the delivery up to `WEBHOOK_MAX_RETRIES` extra times with exponential backoff
+/// (1s, 2s, 4s...), then appends the payload to the dead-letter log.
+async fn deliver(client: &Client, url: &str, payload: &Value) {
+    let max_retries = env::var("WEBHOOK_MAX_RETRIES")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_WEBHOOK_MAX_RETRIES);
+
+    let mut attempt = 0;
+    let last_error = loop {
+        let error = match client.post(url).timeout(Duration::from_secs(10)).json(payload).send().await {
+            Ok(response) if response.status().is_success() => {
+                info!("Webhook notification delivered");
+                return;
+            }
+            Ok(response) => format!("HTTP {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+
+        if attempt >= max_retries {
+            break error;
+        }
+        let backoff = Duration::from_secs(1 << attempt.min(6));
+        warn!("Webhook delivery failed ({}), retrying in {}s", error, backoff.as_secs());
+        tokio::time::sleep(backoff).await;
+        attempt += 1;
+    };
+
+    error!("Webhook delivery failed after {} attempt(s): {}", attempt + 1, last_error);
+    write_dead_letter(payload, &last_error, attempt + 1);
+}
+
+/// Appends an undelivered payload to `WEBHOOK_DEAD_LETTER_PATH` (JSON lines).
+fn write_dead_letter(payload: &Value, error: &str, attempts: u32) {
+    let path = env::var("WEBHOOK_DEAD_LETTER_PATH").unwrap_or_else(|_| DEFAULT_DEAD_LETTER_PATH.to_string());
+    let line = json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "attempts": attempts,
+        "error": error,
+        "payload": payload,
+    });
+
+    let result = Path::new(&path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| {
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            writeln!(file, "{}", line)
+        });
+
+    if let Err(e) = result {
+        error!("Failed to write undelivered webhook payload to {}: {}", path, e);
+    }
+}
@@ -1,51 +1,209 @@
 use reqwest::Client;
 use serde_json::Value;
 use std::env;
+use crate::rate_limit::TrackedSend;
+use crate::error::GastropathError;
+use crate::utils::{Provider, RequestBuilderExt};
 use log::{info, debug, warn};
 
-pub async fn get_cuisine_type(client: &Client, restaurant_name: &str, city: &str) -> Result<String, Box<dyn std::error::Error>> {
+const DEFAULT_MIN_NAME_SIMILARITY: f64 = 0.5;
+
+/// What Yelp knows about the matched business.
+pub struct YelpMatch {
+    /// The categories, or ❓ when there is no (accepted) match.
+    pub cuisine: String,
+    pub rating: Option<f64>,
+}
+
+impl YelpMatch {
+    fn unknown() -> Self {
+        YelpMatch { cuisine: "❓".to_string(), rating: None }
+    }
+}
+
+pub async fn get_cuisine_type(client: &Client, restaurant_name: &str, city: &str, coordinates: Option<(f64, f64)>, request_id: &str) -> Result<YelpMatch, GastropathError> {
     info!("Getting cuisine type for {} in {}", restaurant_name, city);
-    let api_key = env::var("YELP_API_KEY")?;
-    let url = "https://api.yelp.com/v3/businesses/search";
+    let api_key = env::var("YELP_API_KEY").map_err(|_| GastropathError::Other("YELP_API_KEY is not set".to_string()))?;
+    let url = format!("{}/v3/businesses/search", crate::utils::api_base(Provider::Yelp));
     
-    let params = [
-        ("term", restaurant_name),
-        ("location", city),
-        ("limit", "1"),
+    let mut params = vec![
+        ("term", restaurant_name.to_string()),
+        ("limit", "1".to_string()),
     ];
+    match coordinates {
+        Some((lat, lng)) => {
+            params.push(("latitude", lat.to_string()));
+            params.push(("longitude", lng.to_string()));
+        }
+        None => params.push(("location", city.to_string())),
+    }
 
     debug!("Sending request to Yelp API with params: {:?}", params);
 
     let response = client.get(url)
+        .provider_timeout("YELP")
         .query(&params)
         .header("Authorization", format!("Bearer {}", api_key))
-        .send()
+        .send_tracked("YELP")
         .await?
         .json::<Value>()
         .await?;
 
     debug!("Received response from Yelp API: {:?}", response);
+    crate::logging::dump_raw_response(request_id, "yelp_search", &response);
 
     if let Some(error) = response.get("error") {
         let error_description = error["description"].as_str().unwrap_or("Unknown error");
-        warn!("Yelp API error: {}", error_description);
-        return Ok("❓".to_string());
+        return Err(GastropathError::Yelp(format!("Yelp API error: {}", error_description)));
     }
 
     if let Some(businesses) = response["businesses"].as_array() {
         if let Some(business) = businesses.first() {
+            let business_name = business["name"].as_str().unwrap_or_default();
+            let similarity = crate::utils::name_similarity(restaurant_name, business_name);
+            let min_similarity = env::var("YELP_MIN_NAME_SIMILARITY")
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .unwrap_or(DEFAULT_MIN_NAME_SIMILARITY);
+            if similarity < min_similarity {
+                warn!(
+                    "Rejected Yelp match '{}' for {} (similarity {:.2} < {:.2})",
+                    business_name, restaurant_name, similarity, min_similarity
+                );
+                return Ok(YelpMatch::unknown());
+            }
+            debug!("Accepted Yelp match '{}' with similarity {:.2}", business_name, similarity);
+            let rating = business["rating"].as_f64();
+
             if let Some(categories) = business["categories"].as_array() {
                 let cuisine_types: Vec<String> = categories
                     .iter()
                     .filter_map(|category| category["title"].as_str().map(String::from))
                     .collect();
+                let cuisine_types = normalize_categories(cuisine_types);
                 info!("Found cuisine types: {:?}", cuisine_types);
-                return Ok(cuisine_types.join(", "));
+                return Ok(YelpMatch { cuisine: cuisine_types.join(", "), rating });
             }
+            return Ok(YelpMatch { rating, ..YelpMatch::unknown() });
         }
     }
 
     warn!("No cuisine type found for {} in {}", restaurant_name, city);
-    Ok("❓".to_string())
+    Ok(YelpMatch::unknown())
 }
 
+
+/// Applies the steps listed in `YELP_CATEGORY_NORMALIZATION` (comma-separated:
+/// `markup`, `emoji`, `parentheticals`, `trim`, `dedupe`). Steps always run in that
+/// order regardless of how they are listed; nothing is changed when unset.
+fn normalize_categories(categories: Vec<String>) -> Vec<String> {
+    let steps: Vec<String> = env::var("YELP_CATEGORY_NORMALIZATION")
+        .unwrap_or_default()
+        .split(',')
+        .map(|step| step.trim().to_lowercase())
+        .filter(|step| !step.is_empty())
+        .collect();
+    apply_normalization(categories, &steps)
+}
+
+fn apply_normalization(categories: Vec<String>, steps: &[String]) -> Vec<String> {
+    if steps.is_empty() {
+        return categories;
+    }
+    let enabled = |step: &str| steps.iter().any(|s| s == step);
+
+    let mut normalized: Vec<String> = Vec::new();
+    for mut title in categories {
+        if enabled("markup") {
+            title = strip_delimited(&title, '<', '>').replace("&amp;", "&");
+        }
+        if enabled("emoji") {
+            title.retain(|c| c.is_alphanumeric() || c.is_whitespace() || "&'-/,.()[]".contains(c));
+        }
+        if enabled("parentheticals") {
+            title = strip_delimited(&strip_delimited(&title, '(', ')'), '[', ']');
+        }
+        if enabled("trim") {
+            title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        if title.is_empty() {
+            continue;
+        }
+        if enabled("dedupe") && normalized.iter().any(|t| t.eq_ignore_ascii_case(&title)) {
+            continue;
+        }
+        normalized.push(title);
+    }
+
+    debug!("Normalized Yelp categories with steps {:?}: {:?}", steps, normalized);
+    normalized
+}
+
+fn strip_delimited(text: &str, open: char, close: char) -> String {
+    let mut depth = 0;
+    text.chars()
+        .filter(|&c| {
+            if c == open {
+                depth += 1;
+                false
+            } else if c == close && depth > 0 {
+                depth -= 1;
+                false
+            } else {
+                depth == 0
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(categories: &[&str], steps: &str) -> Vec<String> {
+        let steps: Vec<String> = steps.split(',').map(String::from).filter(|s| !s.is_empty()).collect();
+        apply_normalization(categories.iter().map(|c| c.to_string()).collect(), &steps)
+    }
+
+    #[test]
+    fn no_steps_leave_categories_untouched() {
+        assert_eq!(normalize(&[" Pizza ", "pizza"], ""), vec![" Pizza ", "pizza"]);
+    }
+
+    #[test]
+    fn dedupe_ignores_case_and_keeps_the_first() {
+        assert_eq!(normalize(&["Pizza", "Italian", "pizza", "PIZZA"], "dedupe"), vec!["Pizza", "Italian"]);
+    }
+
+    #[test]
+    fn duplicates_left_by_earlier_steps_are_dropped() {
+        assert_eq!(
+            normalize(&["Ramen (Japanese)", "  Ramen ", "<b>Ramen</b>", "🍜 Ramen"], "markup,emoji,parentheticals,trim,dedupe"),
+            vec!["Ramen"]
+        );
+    }
+
+    #[test]
+    fn markup_is_stripped_and_entities_decoded() {
+        assert_eq!(normalize(&["<span class=\"x\">Coffee &amp; Tea</span>"], "markup"), vec!["Coffee & Tea"]);
+    }
+
+    #[test]
+    fn parentheticals_handle_both_delimiters_and_nesting() {
+        assert_eq!(
+            normalize(&["Tapas (Spain) [ES]", "Dim Sum (Cantonese (HK))", "(Closed)"], "parentheticals,trim"),
+            vec!["Tapas", "Dim Sum"]
+        );
+    }
+
+    #[test]
+    fn strip_delimited_ignores_unbalanced_closers() {
+        assert_eq!(strip_delimited("Fish) & (Chips)", '(', ')'), "Fish) & ");
+        assert_eq!(strip_delimited("Bar (open", '(', ')'), "Bar ");
+    }
+
+    #[test]
+    fn steps_run_in_a_fixed_order() {
+        assert_eq!(normalize(&["  Sushi  (Bar) "], "trim,parentheticals"), vec!["Sushi"]);
+    }
+}